@@ -1,82 +1,311 @@
-mod agent;
+mod agents;
+mod backend;
+mod budget;
+mod cancel;
+pub mod commands;
+mod error;
+mod history;
 mod memory;
+#[cfg(test)]
+mod mock;
+mod pipeline;
 mod planner;
+mod provider;
+mod reload;
+mod router;
+mod templates;
 mod tools;
+mod trace;
 mod translator;
 
-use std::sync::Arc;
+pub use budget::DEFAULT_CONTEXT_LENGTH;
+pub use error::AdmeError;
+pub use memory::Namespace;
+pub use trace::Trace;
 
-use agent::Agent;
-use planner::Planner;
-use tokio::sync::Mutex;
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex, RwLock},
+    time::Instant,
+};
+
+use anyhow::Context;
+use notify::RecommendedWatcher;
+use rig::tool::ToolDyn;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
 
-use crate::{
-    adme::{
-        memory::Memory,
-        tools::{RetrieveMemory, StoreMemory},
-        translator::Translator,
-    },
-    filters::filter_think_tag,
+use crate::adme::{
+    agents::Agents,
+    backend::Backend,
+    budget::{BudgetConfig, truncate},
+    cancel::Cancellations,
+    history::{History, HistoryConfig},
+    memory::{Memory, MemoryConfig},
+    pipeline::{Pipeline, PipelineConfig, RunContext},
+    provider::Providers,
+    templates::Templates,
+    tools::StoreMemory,
+    trace::{StageTrace, Traces},
 };
 
+/// Directory Adme keeps its on-disk state in.
+///
+/// Uses `ADME_DATA_DIR` when set, otherwise the app data dir Tauri assigns to
+/// the `com.seedling.dev` identifier.
+pub fn data_dir() -> PathBuf {
+    if let Ok(dir) = std::env::var("ADME_DATA_DIR") {
+        return PathBuf::from(dir);
+    }
+
+    let base = std::env::var("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| {
+            PathBuf::from(std::env::var("HOME").unwrap_or_default()).join(".local/share")
+        });
+    base.join("com.seedling.dev")
+}
+
+/// Cheap to clone, every clone shares the same agents, memory and history.
+///
+/// Nothing here is behind a process-wide lock, so conversations run
+/// concurrently up to the backend's limit.
 #[derive(Clone)]
 pub struct Adme {
-    inner: Arc<Mutex<AdmeInner>>,
+    inner: Arc<AdmeInner>,
 }
 
 struct AdmeInner {
-    planner: Planner,
-    translator: Translator,
+    /// Swapped out whole when the Agents tab sends a new graph or the file changes
+    pipeline: Arc<RwLock<Arc<Pipeline>>>,
     memory: Arc<Memory>,
+    history: Arc<History>,
+    backend: Arc<Backend>,
+    agents: Arc<Agents>,
+    budget: BudgetConfig,
+    /// Lets a chat stop whatever it still has running
+    cancellations: Cancellations,
+    traces: Traces,
+    /// Reloads the config files while it is alive, `None` if watching failed
+    _watcher: Option<RecommendedWatcher>,
 }
 
 impl Adme {
     pub fn new() -> Self {
-        let memory = Arc::new(memory::Memory::new());
-        Self {
-            inner: Arc::new(Mutex::new(AdmeInner {
-                memory: memory.clone(),
-                planner: Planner::new(),
-                translator: Translator::new(),
-            })),
-        }
+        Self::open().expect("Failed to start Adme")
     }
 
-    pub async fn prompt(&self, prompt: &str) -> String {
-        let guard = self.inner.lock().await;
-        let memory = guard.memory.clone();
-
-        let mut response = match guard
-            .planner
-            .prompt(
-                prompt,
-                vec![
-                    Box::new(RetrieveMemory {
-                        memory: memory.clone(),
-                    }),
-                    Box::new(StoreMemory {
-                        memory: memory.clone(),
-                    }),
-                ],
+    /// Loads every config file and the memory store, failing if any is invalid
+    /// or another process has the store open.
+    pub fn open() -> anyhow::Result<Self> {
+        let backend = Arc::new(Backend::from_env().context("Invalid Adme backend configuration")?);
+        let providers = Arc::new(Providers::from_env().context("Invalid LLM provider configuration")?);
+        let agents = Arc::new(
+            Agents::load(
+                agents::config_path(),
+                providers,
+                Templates::load(&templates::dir()).context("Invalid Adme prompt template")?,
             )
-            .await
-        {
-            Ok(Some(res)) => res,
-            _ => String::from("Error processing prompt"),
-        };
+            .context("Invalid Adme agent configuration")?,
+        );
+        let memory = Arc::new(
+            Memory::open(
+                data_dir().join("memory.jsonl"),
+                MemoryConfig::from_env().context("Invalid Adme memory configuration")?,
+                backend.clone(),
+                agents.clone(),
+            )
+            .context("Failed to load Adme memory store")?,
+        );
+        let pipeline_path = pipeline::config_path();
+        let pipeline = Arc::new(RwLock::new(Arc::new(
+            Pipeline::load(&pipeline_path).context("Invalid Adme pipeline configuration")?,
+        )));
+        let watcher = reload::watch(agents.clone(), pipeline.clone(), pipeline_path)
+            .inspect_err(|e| log::warn!("Config files won't be reloaded on change: {:#}", e))
+            .ok();
+
+        Ok(Self {
+            inner: Arc::new(AdmeInner {
+                memory,
+                history: Arc::new(History::new(HistoryConfig::default(), agents.clone())),
+                pipeline,
+                backend,
+                agents,
+                budget: BudgetConfig::from_env().context("Invalid Adme context length")?,
+                cancellations: Cancellations::default(),
+                traces: Traces::open(data_dir().join("traces.jsonl")).context("Failed to load Adme traces")?,
+                _watcher: watcher,
+            }),
+        })
+    }
+
+    /// Shared handle to the long term memory store.
+    pub fn memory(&self) -> Arc<Memory> {
+        self.inner.memory.clone()
+    }
+
+    pub fn pipeline(&self) -> PipelineConfig {
+        self.inner.pipeline.read().unwrap().config().clone()
+    }
 
-        response = filter_think_tag(&response);
+    /// Validates and saves `config`, then uses it for every prompt from now on.
+    pub async fn set_pipeline(&self, config: PipelineConfig) -> anyhow::Result<()> {
+        let pipeline = Pipeline::new(config)?;
+        pipeline.save(&pipeline::config_path()).await?;
+        *self.inner.pipeline.write().unwrap() = Arc::new(pipeline);
+        Ok(())
+    }
 
-        let processor_prompt = format!("Context: {}\nUser Prompt: {}", response, prompt);
+    /// Forgets the conversation history of the chat in `namespace`.
+    pub async fn reset_history(&self, namespace: &Namespace) {
+        self.inner.history.clear(&session_key(namespace)).await;
+    }
 
-        response = match guard.translator.prompt(&processor_prompt, vec![]).await {
-            Ok(Some(res)) => res,
-            _ => String::from("Error processing prompt"),
+    /// Up to `limit` of the latest traces across every chat, newest first.
+    pub async fn traces(&self, limit: usize) -> Vec<Trace> {
+        self.inner.traces.recent(None, limit).await
+    }
+
+    pub async fn trace(&self, id: &str) -> Option<Trace> {
+        self.inner.traces.get(id).await
+    }
+
+    /// Trace of the last prompt answered in the chat of `namespace`.
+    pub async fn last_trace(&self, namespace: &Namespace) -> Option<Trace> {
+        self.inner.traces.recent(Some(&session_key(namespace)), 1).await.pop()
+    }
+
+    /// Stops every prompt still running in the chat of `namespace`, telling
+    /// its caller `reason`. Returns whether there was anything to stop.
+    pub fn cancel(&self, namespace: &Namespace, reason: &str) -> bool {
+        self.inner.cancellations.cancel(&session_key(namespace), reason)
+    }
+
+    /// Answers `prompt`, with memory limited to what `namespace` may see.
+    ///
+    /// Every other pipeline stage runs to completion first, then the output
+    /// stage's reply arrives in chunks as it is generated. `<think>` blocks
+    /// are already removed, and the channel closes once the reply is complete.
+    /// A failure, including the prompt being [cancelled](Self::cancel),
+    /// arrives as the last item. Either way a [`Trace`] of the request is kept.
+    pub fn prompt_stream(
+        &self,
+        prompt: &str,
+        namespace: &Namespace,
+    ) -> UnboundedReceiver<Result<String, AdmeError>> {
+        let (tokens, receiver) = mpsc::unbounded_channel();
+        let adme = self.clone();
+        let prompt = prompt.to_string();
+        let namespace = namespace.clone();
+        tokio::spawn(async move {
+            let session = session_key(&namespace);
+            let mut trace = Trace::new(&session, &prompt);
+            let started = Instant::now();
+            let stages = Mutex::default();
+            let cancel = adme.inner.cancellations.handle(&session);
+            let result = tokio::select! {
+                result = adme.respond(&prompt, &namespace, &tokens, &stages) => result,
+                // Dropping the response future aborts whichever stage is running
+                reason = cancel.cancelled() => Err(AdmeError::Cancelled { reason }),
+            };
+            adme.inner.cancellations.release(&session, cancel);
+
+            trace.stages = stages.into_inner().unwrap();
+            trace.error = result.as_ref().err().map(ToString::to_string);
+            trace.duration_ms = started.elapsed().as_millis() as u64;
+            if let Err(e) = result {
+                let _ = tokens.send(Err(e));
+            }
+            if let Err(e) = adme.inner.traces.record(trace).await {
+                log::warn!("Failed to save trace: {:#}", e);
+            }
+        });
+        receiver
+    }
+
+    async fn respond(
+        &self,
+        prompt: &str,
+        namespace: &Namespace,
+        tokens: &UnboundedSender<Result<String, AdmeError>>,
+        trace: &Mutex<Vec<StageTrace>>,
+    ) -> Result<(), AdmeError> {
+        let inner = &self.inner;
+        let memory = inner.memory.clone();
+        let history = inner.history.clone();
+        let session = session_key(namespace);
+
+        // Earlier turns let the stages resolve follow-ups like "and the second one?"
+        let conversation = history.render(&session).await;
+        let chit_chat = router::is_chit_chat(prompt, history.last_reply(&session).await.as_deref());
+        let pipeline = inner.pipeline.read().unwrap().clone();
+        let context = RunContext {
+            prompt,
+            conversation: &conversation,
+            memory: &memory,
+            namespace,
+            backend: &inner.backend,
+            providers: inner.agents.providers(),
+            budget: &inner.budget,
+            templates: inner.agents.templates(),
+            chit_chat,
+            trace,
         };
+        let response = pipeline.run(&context, tokens).await?;
+
+        history.record(&session, prompt, &response).await;
+        let backend = inner.backend.clone();
+        tokio::spawn(async move {
+            let _permit = backend.permit().await;
+            if let Err(e) = backend.deadline(history.compact(&session)).await {
+                log::warn!("Failed to summarise conversation history: {:#}", e);
+            }
+        });
 
-        // let memory_entry = format!("User Prompt: {}\nAgent Response: {}", prompt, filter_think_tag(&response));
+        // Small talk has nothing to learn from, and would only hold up the next prompt
+        if chit_chat {
+            return Ok(());
+        }
+
+        // Learn from the exchange in the background so the reply isn't held up
+        let memory_entry = truncate(
+            &inner.agents.templates().extract.render(&[("prompt", prompt), ("response", response.as_str())]),
+            inner.budget.prompt_tokens(inner.agents.get().extractor.context_length),
+        );
+        tokio::spawn(extract_memories(
+            memory,
+            inner.backend.clone(),
+            memory_entry,
+            namespace.clone(),
+        ));
+
+        Ok(())
+    }
+}
+
+/// Conversation history is kept per chat, so every user in a group shares it.
+fn session_key(namespace: &Namespace) -> String {
+    namespace.chat.clone().unwrap_or_default()
+}
+
+/// Runs the memory agent over a finished exchange so it stores any new atomic truths.
+async fn extract_memories(
+    memory: Arc<Memory>,
+    backend: Arc<Backend>,
+    exchange: String,
+    namespace: Namespace,
+) {
+    let _permit = backend.permit().await;
+    let tools: Vec<Box<dyn ToolDyn>> = vec![Box::new(StoreMemory {
+        memory: memory.clone(),
+        source: String::from("extractor"),
+        namespace,
+        policy: Some(memory.merge_policy().unattended()),
+    })];
 
-        // let _ = memory.prompt(&memory_entry, vec![Box::new(StoreMemory { memory: memory.clone() })]).await;
-        response
+    match backend.deadline(memory.extract(&exchange, tools)).await {
+        Ok(Some(learned)) => log::info!("Learned from conversation:\n{}", learned),
+        Ok(None) => log::debug!("Nothing new to learn from conversation"),
+        Err(e) => log::warn!("Memory extraction failed: {:#}", e),
     }
 }
@@ -0,0 +1,260 @@
+//! Model, sampling and preamble settings for the LLM agents.
+//!
+//! Pipeline stages carry their own [`AgentSettings`]. The agents that look
+//! after memory and history are configured in `ADME_AGENTS` (default
+//! `agents.json` in the data dir). Any agent left out of that file keeps the
+//! built-in settings below. Both files are reloaded when they change, see
+//! [`reload`](super::reload). The prompts these agents are sent come from
+//! [`templates`](super::templates), and preambles may use the global
+//! template variables, `{{date}}` and `{{user}}`.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use rig::{client::CompletionClient, tool::ToolDyn};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::adme::{
+    data_dir,
+    provider::{ProviderAgent, ProviderKind, Providers},
+    templates::{self, Template, TemplateError, Templates},
+};
+
+/// Model used by every built-in agent.
+pub const DEFAULT_MODEL: &str = "qwen3:30b";
+/// Enough for the planner to retrieve a memory, correct it and still store something new.
+pub const DEFAULT_MAX_TURNS: usize = 4;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AgentSettings {
+    #[serde(default)]
+    pub provider: ProviderKind,
+    pub model: String,
+    pub preamble: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    /// Context window to ask Ollama for, in tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_length: Option<u64>,
+    /// Passed through to the provider as is, for sampling options like `top_p`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub additional_params: Option<Value>,
+    /// Rounds of tool calls the agent may chain before it has to answer,
+    /// [`DEFAULT_MAX_TURNS`] if `None`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_turns: Option<usize>,
+}
+
+impl AgentSettings {
+    pub fn new(preamble: &str) -> Self {
+        Self {
+            provider: ProviderKind::default(),
+            model: DEFAULT_MODEL.to_string(),
+            preamble: preamble.to_string(),
+            temperature: None,
+            context_length: None,
+            additional_params: None,
+            max_turns: None,
+        }
+    }
+
+    /// Checks that the preamble only uses the global template variables.
+    pub fn validate(&self, name: &str) -> Result<(), TemplateError> {
+        Template::new(&format!("{} preamble", name), &self.preamble, &[]).map(|_| ())
+    }
+
+    /// Builds an agent with these settings and `tools` on the configured provider.
+    pub fn agent(&self, providers: &Providers, tools: Vec<Box<dyn ToolDyn>>) -> ProviderAgent {
+        let params = self.params();
+        let preamble = templates::render(&self.preamble, templates::global);
+        let max_turns = self.max_turns.unwrap_or(DEFAULT_MAX_TURNS);
+        match self.provider {
+            ProviderKind::Ollama => {
+                let mut builder = providers
+                    .ollama
+                    .agent(&self.model)
+                    .preamble(&preamble)
+                    .tools(tools)
+                    .default_max_turns(max_turns);
+                if let Some(temperature) = self.temperature {
+                    builder = builder.temperature(temperature);
+                }
+                if let Some(params) = params {
+                    builder = builder.additional_params(params);
+                }
+                ProviderAgent::Ollama(builder.build())
+            }
+            ProviderKind::OpenAi => {
+                let mut builder = providers
+                    .openai
+                    .agent(&self.model)
+                    .preamble(&preamble)
+                    .tools(tools)
+                    .default_max_turns(max_turns);
+                if let Some(temperature) = self.temperature {
+                    builder = builder.temperature(temperature);
+                }
+                if let Some(params) = params {
+                    builder = builder.additional_params(params);
+                }
+                ProviderAgent::OpenAi(builder.build())
+            }
+        }
+    }
+
+    fn params(&self) -> Option<Value> {
+        // OpenAI-compatible servers fix the context length when they load the model
+        let (ProviderKind::Ollama, Some(context_length)) = (self.provider, self.context_length) else {
+            return self.additional_params.clone();
+        };
+        // Ollama only reads the context length from its options
+        let mut params = self.additional_params.clone().unwrap_or_else(|| json!({}));
+        params["options"]["num_ctx"] = json!(context_length);
+        Some(params)
+    }
+}
+
+/// Agents outside the pipeline.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct AgentsConfig {
+    /// Pulls atomic truths out of each finished exchange
+    pub extractor: AgentSettings,
+    /// Merges a new memory into a similar existing one
+    pub combiner: AgentSettings,
+    /// Digests stale memories during consolidation
+    pub consolidator: AgentSettings,
+    /// Folds old turns into the conversation summary
+    pub summariser: AgentSettings,
+}
+
+impl Default for AgentsConfig {
+    fn default() -> Self {
+        Self {
+            extractor: AgentSettings::new("Role: You are a Memory Architect for a Sovereign AI.\nTask: Analyze the provided conversation and extract discrete, high signal \"Atomic Truths\". Call store_memory with each of these truths, then reply with the truths you stored, one per line, or nothing if there were none.\nInstructions:\n1. Ignore filler, politeness, and temporary statements.\n2. Format each truth strictly as: Subject | Attribute | Value | Context | Rationale.\n3. Rationale must explain **why** this was concluded (e.g., \"User explicitly stated,\" or \"Inferred from repeated code patterns\").\n4. If a new truth contracdicts an old one, note it in the Context.\n5. Resolve all pronouns. Replace 'I/Me/My' with 'User' and 'You/Your' with 'Assistant'. Every truth must be an objective statement about a specific entity.\nExample\nInput:\"Actually, let's switch the 3D renderer to Vulkan. OpenGL is too slow for this geometry kernel.\"\nOutput:3D renderer | technology | Vulkan | Project Kernel Development | Switched from OpenGL due to performance bottlenecks in geometry processing."),
+            combiner: AgentSettings::new("Role: You are looking at a new memory and one of your current memories.\nTask: combine them into a single, concise memory that captures all essential information from both.\nInstructions:\n1. If you find any information conflicting, go with the more up to date info\n2. If there is little to nothing to gain by updating the same don't bother updating it"),
+            consolidator: AgentSettings::new("Role: You are tidying up old memories that have not been used in a long time.\nTask: summarise them into a single, concise memory that keeps every fact still likely to matter.\nInstructions:\n1. Drop details that are clearly temporary or superseded.\n2. Output only the summary."),
+            summariser: AgentSettings::new("Role: You are keeping notes on a long conversation between a user and an AI named Adme.\nTask: update the running summary with the exchanges provided.\nInstructions:\n1. Keep names, decisions, open questions and anything the user may refer back to.\n2. Output only the updated summary."),
+        }
+    }
+}
+
+/// The current [`AgentsConfig`], swapped out whole on reload, the provider
+/// clients every agent is built on and the templates for their prompts.
+pub struct Agents {
+    /// File the config is read from, `None` always uses the defaults
+    path: Option<PathBuf>,
+    config: RwLock<Arc<AgentsConfig>>,
+    providers: Arc<Providers>,
+    templates: Templates,
+}
+
+impl Agents {
+    /// The built-in agents and templates on `providers`, without any files.
+    pub fn new(providers: Arc<Providers>) -> Self {
+        Self {
+            path: None,
+            config: RwLock::new(Arc::new(AgentsConfig::default())),
+            providers,
+            templates: Templates::default(),
+        }
+    }
+
+    /// Loads the config at `path`, or the defaults if there is no file.
+    pub fn load(
+        path: impl Into<PathBuf>,
+        providers: Arc<Providers>,
+        templates: Templates,
+    ) -> anyhow::Result<Self> {
+        let path = path.into();
+        Ok(Self {
+            config: RwLock::new(Arc::new(read(&path)?)),
+            path: Some(path),
+            providers,
+            templates,
+        })
+    }
+
+    pub fn get(&self) -> Arc<AgentsConfig> {
+        self.config.read().unwrap().clone()
+    }
+
+    pub fn providers(&self) -> &Providers {
+        &self.providers
+    }
+
+    pub fn templates(&self) -> &Templates {
+        &self.templates
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Re-reads the config file, keeping the current config if it is invalid.
+    pub fn reload(&self) -> anyhow::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let config = read(path)?;
+        *self.config.write().unwrap() = Arc::new(config);
+        Ok(())
+    }
+}
+
+/// Where the agent config lives, `ADME_AGENTS` or `agents.json` in the data dir.
+pub fn config_path() -> PathBuf {
+    std::env::var("ADME_AGENTS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| data_dir().join("agents.json"))
+}
+
+fn read(path: &Path) -> anyhow::Result<AgentsConfig> {
+    if !path.exists() {
+        return Ok(AgentsConfig::default());
+    }
+    let config: AgentsConfig = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    config.extractor.validate("extractor")?;
+    config.combiner.validate("combiner")?;
+    config.consolidator.validate("consolidator")?;
+    config.summariser.validate("summariser")?;
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_agents_keep_their_defaults() {
+        let config: AgentsConfig = serde_json::from_value(json!({
+            "extractor": {"model": "qwen3:4b", "preamble": "Extract facts.", "temperature": 0.2}
+        }))
+        .unwrap();
+        assert_eq!(config.extractor.model, "qwen3:4b");
+        assert_eq!(config.combiner, AgentsConfig::default().combiner);
+    }
+
+    #[test]
+    fn test_preambles_may_only_use_global_variables() {
+        assert!(AgentSettings::new("You are talking to {{user}} on {{date}}.").validate("extractor").is_ok());
+        assert!(matches!(
+            AgentSettings::new("You are talking to {{name}}.").validate("extractor"),
+            Err(TemplateError::UnknownVariable { name, .. }) if name == "name"
+        ));
+    }
+
+    #[test]
+    fn test_context_length_goes_into_ollama_options() {
+        let mut settings = AgentSettings::new("");
+        settings.context_length = Some(8192);
+        settings.additional_params = Some(json!({"no_think": false}));
+        assert_eq!(
+            settings.params(),
+            Some(json!({"no_think": false, "options": {"num_ctx": 8192}}))
+        );
+    }
+}
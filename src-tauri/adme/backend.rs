@@ -0,0 +1,71 @@
+//! Shared access to the LLM server.
+//!
+//! Conversations run concurrently, but the local Ollama server only serves a
+//! few requests at a time before queueing or running out of VRAM. Every
+//! top-level LLM stage holds a permit from here while it runs. Calls nested
+//! inside a stage, such as a memory merge triggered by a tool call, are
+//! covered by that stage's permit.
+//!
+//! Background jobs, such as memory extraction and consolidation, hold a permit
+//! too, so each LLM call they make has a [deadline](Backend::deadline). A
+//! stalled server would otherwise keep their permits forever and every new
+//! prompt would queue behind them.
+
+use std::{future::Future, time::Duration};
+
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// Stages allowed to talk to the backend at once unless configured otherwise.
+const DEFAULT_MAX_CONCURRENT: usize = 2;
+/// How long a background LLM call may run unless configured otherwise.
+const DEFAULT_BACKGROUND_TIMEOUT_SECS: u64 = 180;
+
+pub struct Backend {
+    permits: Semaphore,
+    background_timeout: Duration,
+}
+
+impl Backend {
+    pub fn new(max_concurrent: usize) -> Self {
+        Self {
+            permits: Semaphore::new(max_concurrent.max(1)),
+            background_timeout: Duration::from_secs(DEFAULT_BACKGROUND_TIMEOUT_SECS),
+        }
+    }
+
+    /// Reads the limit from `ADME_MAX_CONCURRENT_LLM_REQUESTS` and the
+    /// background deadline from `ADME_BACKGROUND_TIMEOUT_SECS`.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let max_concurrent = match std::env::var("ADME_MAX_CONCURRENT_LLM_REQUESTS") {
+            Ok(value) => value.parse()?,
+            Err(_) => DEFAULT_MAX_CONCURRENT,
+        };
+        let mut backend = Self::new(max_concurrent);
+        if let Ok(value) = std::env::var("ADME_BACKGROUND_TIMEOUT_SECS") {
+            backend.background_timeout = Duration::from_secs(value.parse()?);
+        }
+        Ok(backend)
+    }
+
+    /// Waits until the backend can take another request.
+    pub async fn permit(&self) -> SemaphorePermit<'_> {
+        self.permits
+            .acquire()
+            .await
+            .expect("the backend semaphore is never closed")
+    }
+
+    /// Runs a background LLM call, failing it if it outlives the background deadline.
+    pub async fn deadline<T>(&self, call: impl Future<Output = anyhow::Result<T>>) -> anyhow::Result<T> {
+        tokio::time::timeout(self.background_timeout, call)
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|result| result)
+    }
+}
+
+impl Default for Backend {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_CONCURRENT)
+    }
+}
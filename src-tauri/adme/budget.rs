@@ -0,0 +1,105 @@
+//! Keeping agent prompts inside the model's context window.
+//!
+//! Tokens are estimated with [`estimate_tokens`] rather than counted by the
+//! model's tokenizer, which is close enough to stay clear of the limit. Each
+//! section of a stage prompt has its own rule:
+//!
+//! - conversation history keeps the newest turns within its budget and folds
+//!   older ones into a summary, see [`History`](super::history::History)
+//! - the output of an upstream stage is cut to `max_stage_output_tokens`
+//! - retrieved memories keep to `max_tool_output_tokens`, dropping the lowest
+//!   ranked first, see [`RetrieveMemory`](super::tools::RetrieveMemory)
+//!
+//! A prompt that still doesn't fit leaves the conversation history out, and
+//! is sent with a warning if even that isn't enough.
+
+/// Context window `ProcessManager::start_ollama` starts Ollama with.
+pub const DEFAULT_CONTEXT_LENGTH: usize = 24576;
+
+#[derive(Clone, Debug)]
+pub struct BudgetConfig {
+    /// Context window of the backend, for stages that don't set their own
+    pub context_length: usize,
+    /// Room kept free in the context window for the reply
+    pub reply_tokens: usize,
+    pub max_stage_output_tokens: usize,
+    pub max_tool_output_tokens: usize,
+}
+
+impl Default for BudgetConfig {
+    fn default() -> Self {
+        Self {
+            context_length: DEFAULT_CONTEXT_LENGTH,
+            reply_tokens: 2048,
+            max_stage_output_tokens: 4096,
+            max_tool_output_tokens: 2048,
+        }
+    }
+}
+
+impl BudgetConfig {
+    /// Reads the context window from `OLLAMA_CONTEXT_LENGTH`, like Ollama does.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let mut config = Self::default();
+        if let Ok(value) = std::env::var("OLLAMA_CONTEXT_LENGTH") {
+            config.context_length = value.parse()?;
+        }
+        Ok(config)
+    }
+
+    /// Tokens a prompt may take up in a context window of `context_length`,
+    /// or the backend's if `None`.
+    pub fn prompt_tokens(&self, context_length: Option<u64>) -> usize {
+        context_length
+            .map_or(self.context_length, |length| length as usize)
+            .saturating_sub(self.reply_tokens)
+    }
+}
+
+/// Rough token count, about four characters per token for English text.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Cuts `text` down to about `max_tokens`, noting how much was left out.
+pub fn truncate(text: &str, max_tokens: usize) -> String {
+    let tokens = estimate_tokens(text);
+    if tokens <= max_tokens {
+        return text.to_string();
+    }
+    let end = text
+        .char_indices()
+        .nth(max_tokens * 4)
+        .map_or(text.len(), |(end, _)| end);
+    format!(
+        "{}\n[… about {} more tokens left out]",
+        &text[..end],
+        tokens - max_tokens
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_short_text_is_kept_whole() {
+        assert_eq!(truncate("twelve chars", 3), "twelve chars");
+    }
+
+    #[test]
+    fn test_long_text_notes_what_was_cut() {
+        let text = "a".repeat(100);
+        assert_eq!(
+            truncate(&text, 5),
+            format!("{}\n[… about 20 more tokens left out]", "a".repeat(20))
+        );
+    }
+
+    #[test]
+    fn test_stage_context_length_wins_over_the_backend() {
+        let config = BudgetConfig::default();
+        assert_eq!(config.prompt_tokens(None), DEFAULT_CONTEXT_LENGTH - 2048);
+        assert_eq!(config.prompt_tokens(Some(8192)), 8192 - 2048);
+    }
+}
@@ -0,0 +1,101 @@
+//! Stopping prompts that are still running.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::watch;
+
+/// Cancels every request that holds a clone, with a reason for the user.
+#[derive(Clone)]
+pub struct CancelHandle(Arc<watch::Sender<Option<String>>>);
+
+impl CancelHandle {
+    fn new() -> Self {
+        Self(Arc::new(watch::channel(None).0))
+    }
+
+    pub fn cancel(&self, reason: &str) {
+        self.0.send_replace(Some(reason.to_string()));
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.0.borrow().is_some()
+    }
+
+    /// Resolves with the reason once the handle is cancelled.
+    pub async fn cancelled(&self) -> String {
+        let mut receiver = self.0.subscribe();
+        let reason = receiver
+            .wait_for(Option::is_some)
+            .await
+            .expect("the handle owns the sender");
+        reason.clone().unwrap_or_default()
+    }
+}
+
+/// The cancel handle of each chat with prompts in flight.
+#[derive(Default)]
+pub struct Cancellations {
+    sessions: Mutex<HashMap<String, CancelHandle>>,
+}
+
+impl Cancellations {
+    /// Handle for a new request in `session`, shared with the others still running there.
+    pub fn handle(&self, session: &str) -> CancelHandle {
+        let mut sessions = self.sessions.lock().unwrap();
+        let handle = sessions.entry(session.to_string()).or_insert_with(CancelHandle::new);
+        if handle.is_cancelled() {
+            *handle = CancelHandle::new();
+        }
+        handle.clone()
+    }
+
+    /// Cancels everything running in `session`, returning whether anything was.
+    pub fn cancel(&self, session: &str, reason: &str) -> bool {
+        let Some(handle) = self.sessions.lock().unwrap().remove(session) else {
+            return false;
+        };
+        // Only the map holding it means no request is using the handle
+        let running = Arc::strong_count(&handle.0) > 1;
+        handle.cancel(reason);
+        running
+    }
+
+    /// Drops the handle of `session` once no request uses it any more.
+    pub fn release(&self, session: &str, handle: CancelHandle) {
+        let mut sessions = self.sessions.lock().unwrap();
+        drop(handle);
+        if let Some(current) = sessions.get(session)
+            && Arc::strong_count(&current.0) == 1
+        {
+            sessions.remove(session);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_cancel_reaches_running_requests() {
+        let cancellations = Cancellations::default();
+        let handle = cancellations.handle("chat");
+
+        assert!(cancellations.cancel("chat", "Cancelled with /cancel."));
+        assert_eq!(handle.cancelled().await, "Cancelled with /cancel.");
+        // The next request starts afresh
+        assert!(!cancellations.handle("chat").is_cancelled());
+    }
+
+    #[test]
+    fn test_nothing_to_cancel_once_released() {
+        let cancellations = Cancellations::default();
+        let handle = cancellations.handle("chat");
+        cancellations.release("chat", handle);
+
+        assert!(!cancellations.cancel("chat", "Cancelled with /cancel."));
+    }
+}
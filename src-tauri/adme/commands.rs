@@ -0,0 +1,112 @@
+//! Tauri commands for inspecting and correcting Adme's long term memory,
+//! configuring its agent pipeline and looking back at how it answered.
+
+use std::path::Path;
+
+use tauri::State;
+
+use crate::adme::{
+    Adme, Trace,
+    memory::{ConsolidationReport, MyDoc},
+    pipeline::{PipelineConfig, PipelineGraph},
+};
+
+/// Source recorded on memories edited by hand from the desktop app.
+const SOURCE: &str = "user";
+/// Traces listed when the app doesn't ask for a number.
+const DEFAULT_TRACE_LIMIT: usize = 20;
+
+#[tauri::command]
+pub async fn list_memories(adme: State<'_, Adme>) -> Result<Vec<MyDoc>, String> {
+    Ok(adme.memory().list_memories().await)
+}
+
+#[tauri::command]
+pub async fn get_memory(id: String, adme: State<'_, Adme>) -> Result<Option<MyDoc>, String> {
+    Ok(adme.memory().get_memory(&id).await)
+}
+
+#[tauri::command]
+pub async fn update_memory(
+    id: String,
+    summary: String,
+    adme: State<'_, Adme>,
+) -> Result<MyDoc, String> {
+    adme.memory()
+        .update_memory(&id, &summary, SOURCE)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn delete_memory(id: String, adme: State<'_, Adme>) -> Result<MyDoc, String> {
+    adme.memory()
+        .delete_memory(&id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn rollback_memory(
+    id: String,
+    version: usize,
+    adme: State<'_, Adme>,
+) -> Result<MyDoc, String> {
+    adme.memory()
+        .rollback_memory(&id, version, SOURCE)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn export_memories(
+    path: String,
+    include_embeddings: bool,
+    adme: State<'_, Adme>,
+) -> Result<usize, String> {
+    adme.memory()
+        .export_jsonl(Path::new(&path), include_embeddings)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn import_memories(path: String, adme: State<'_, Adme>) -> Result<usize, String> {
+    adme.memory()
+        .import_jsonl(Path::new(&path))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn consolidate_memories(adme: State<'_, Adme>) -> Result<ConsolidationReport, String> {
+    adme.memory()
+        .consolidate()
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_pipeline(adme: State<'_, Adme>) -> Result<PipelineConfig, String> {
+    Ok(adme.pipeline())
+}
+
+/// Replaces the pipeline with the graph drawn in the Agents tab.
+#[tauri::command]
+pub async fn set_pipeline(graph: PipelineGraph, adme: State<'_, Adme>) -> Result<PipelineConfig, String> {
+    adme.set_pipeline(PipelineConfig::from(graph))
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(adme.pipeline())
+}
+
+/// The latest request traces, newest first.
+#[tauri::command]
+pub async fn list_traces(limit: Option<usize>, adme: State<'_, Adme>) -> Result<Vec<Trace>, String> {
+    Ok(adme.traces(limit.unwrap_or(DEFAULT_TRACE_LIMIT)).await)
+}
+
+#[tauri::command]
+pub async fn get_trace(id: String, adme: State<'_, Adme>) -> Result<Option<Trace>, String> {
+    Ok(adme.trace(&id).await)
+}
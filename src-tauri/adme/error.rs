@@ -0,0 +1,168 @@
+//! Failures surfaced to whoever asked Adme something.
+//!
+//! Agents report plain [`anyhow::Error`]s. [`AdmeError::classify`] sorts them
+//! into the handful of cases a user can actually do something about.
+
+use rig::completion::PromptError;
+
+use crate::adme::provider::ProviderKind;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AdmeError {
+    #[error("{stage} could not reach the LLM backend at {url}")]
+    BackendUnreachable {
+        stage: String,
+        provider: ProviderKind,
+        url: String,
+    },
+    #[error("{stage} asked for a model the backend doesn't have: {}", model.as_deref().unwrap_or("unknown"))]
+    ModelMissing { stage: String, model: Option<String> },
+    #[error("{stage} tool call failed: {message}")]
+    ToolFailed { stage: String, message: String },
+    #[error("{stage} timed out")]
+    Timeout { stage: String },
+    #[error("{stage} prompt overflowed the model's context window")]
+    ContextOverflow { stage: String },
+    #[error("{stage} failed: {message}")]
+    Other { stage: String, message: String },
+    /// The caller stopped the prompt, `reason` says how
+    #[error("cancelled: {reason}")]
+    Cancelled { reason: String },
+}
+
+impl AdmeError {
+    /// Works out what went wrong with `stage`, running on `provider` at `url`,
+    /// from the error it returned.
+    pub fn classify(stage: &str, provider: ProviderKind, url: &str, error: &anyhow::Error) -> Self {
+        let stage = stage.to_string();
+        if error.is::<tokio::time::error::Elapsed>() {
+            return Self::Timeout { stage };
+        }
+        if let Some(PromptError::ToolError(e)) = error.downcast_ref::<PromptError>() {
+            return Self::ToolFailed {
+                stage,
+                message: e.to_string(),
+            };
+        }
+
+        // Providers only hand back text, so the rest is recognised by its wording
+        let message = format!("{:#}", error);
+        let lower = message.to_lowercase();
+        if lower.contains("connection refused")
+            || lower.contains("error sending request")
+            || lower.contains("dns error")
+            || lower.contains("failed to connect")
+        {
+            Self::BackendUnreachable {
+                stage,
+                provider,
+                url: url.to_string(),
+            }
+        } else if lower.contains("model") && lower.contains("not found") {
+            Self::ModelMissing {
+                stage,
+                model: quoted(&message),
+            }
+        } else if lower.contains("context")
+            && (lower.contains("length") || lower.contains("exceed") || lower.contains("too long"))
+        {
+            Self::ContextOverflow { stage }
+        } else if lower.contains("timed out") || lower.contains("timeout") {
+            Self::Timeout { stage }
+        } else {
+            Self::Other { stage, message }
+        }
+    }
+
+    /// What to tell the user, phrased so they know what to try next.
+    pub fn user_message(&self) -> String {
+        match self {
+            Self::BackendUnreachable { provider: ProviderKind::Ollama, url, .. } => format!(
+                "I can't reach the model server at {}. Check that Ollama is running and OLLAMA_API_BASE_URL points at it.",
+                url
+            ),
+            Self::BackendUnreachable { provider: ProviderKind::OpenAi, url, .. } => format!(
+                "I can't reach the model server at {}. Check that the OpenAI-compatible server, such as llama-swap, is running and ADME_OPENAI_BASE_URL points at it.",
+                url
+            ),
+            Self::ModelMissing { model: Some(model), .. } => format!(
+                "The model {} isn't installed. Run `ollama pull {}` and try again.",
+                model, model
+            ),
+            Self::ModelMissing { model: None, .. } => {
+                String::from("The model I use isn't installed. Pull it with `ollama pull` and try again.")
+            }
+            Self::ToolFailed { message, .. } => format!(
+                "One of my memory tools failed ({}). Try again, and check the logs if it keeps happening.",
+                message
+            ),
+            Self::Timeout { stage } => format!(
+                "The {} took too long to answer. Try again, or use a smaller model if this keeps happening.",
+                stage
+            ),
+            Self::ContextOverflow { .. } => String::from(
+                "This conversation no longer fits in the model's context window. Reset the conversation and try again.",
+            ),
+            Self::Other { stage, .. } => format!(
+                "Something went wrong in the {}. Check the logs for details.",
+                stage
+            ),
+            Self::Cancelled { reason } => reason.clone(),
+        }
+    }
+}
+
+/// First `"..."` or `'...'` quoted section of `message`.
+fn quoted(message: &str) -> Option<String> {
+    let start = message.find(['"', '\''])?;
+    let quote = message[start..].chars().next()?;
+    let rest = &message[start + 1..];
+    let end = rest.find(quote)?;
+    Some(rest[..end].to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adme::provider::{DEFAULT_OLLAMA_BASE_URL as OLLAMA_URL, DEFAULT_OPENAI_BASE_URL};
+
+    #[test]
+    fn test_recognises_missing_model() {
+        let error = anyhow::anyhow!("ProviderError: model \"qwen3:30b\" not found, try pulling it first");
+        match AdmeError::classify("planner", ProviderKind::Ollama, OLLAMA_URL, &error) {
+            AdmeError::ModelMissing { model, .. } => assert_eq!(model.as_deref(), Some("qwen3:30b")),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_recognises_unreachable_backend() {
+        let error = anyhow::anyhow!("tcp connect error: Connection refused (os error 111)")
+            .context("HttpError: error sending request");
+        let url = DEFAULT_OPENAI_BASE_URL;
+        let classified = AdmeError::classify("translator", ProviderKind::OpenAi, url, &error);
+        assert!(matches!(
+            &classified,
+            AdmeError::BackendUnreachable { url: reported, .. } if reported == url
+        ));
+        assert!(classified.user_message().contains("ADME_OPENAI_BASE_URL"));
+    }
+
+    #[test]
+    fn test_recognises_context_overflow() {
+        let error = anyhow::anyhow!("input length exceeds the context length");
+        assert!(matches!(
+            AdmeError::classify("planner", ProviderKind::Ollama, OLLAMA_URL, &error),
+            AdmeError::ContextOverflow { .. }
+        ));
+    }
+
+    #[test]
+    fn test_keeps_unknown_errors() {
+        let error = anyhow::anyhow!("something odd");
+        match AdmeError::classify("planner", ProviderKind::Ollama, OLLAMA_URL, &error) {
+            AdmeError::Other { message, .. } => assert_eq!(message, "something odd"),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
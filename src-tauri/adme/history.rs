@@ -0,0 +1,197 @@
+//! Per-session conversation history fed to the agents.
+//!
+//! Each Telegram chat or terminal session keeps its recent exchanges so
+//! follow-up questions make sense. Older exchanges are folded into a running
+//! summary once the session outgrows its token budget.
+
+use std::{collections::HashMap, sync::Arc};
+
+use tokio::sync::Mutex;
+
+use crate::{
+    adme::{agents::Agents, budget::estimate_tokens, templates::Template},
+    filters::filter_think_tag,
+};
+
+#[derive(Clone, Debug)]
+pub struct HistoryConfig {
+    /// Budget for the rendered history in each agent prompt
+    pub max_tokens: usize,
+    /// Exchanges never folded into the summary, however long they are
+    pub keep_recent_turns: usize,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            max_tokens: 4096,
+            keep_recent_turns: 4,
+        }
+    }
+}
+
+/// One exchange between the user and Adme.
+#[derive(Clone, PartialEq)]
+pub struct Turn {
+    pub user: String,
+    pub assistant: String,
+}
+
+impl Turn {
+    fn render(&self, template: &Template) -> String {
+        template.render(&[("prompt", self.user.as_str()), ("response", self.assistant.as_str())])
+    }
+}
+
+#[derive(Default)]
+struct Conversation {
+    /// Summary of exchanges that were dropped from `turns`
+    summary: Option<String>,
+    turns: Vec<Turn>,
+}
+
+pub struct History {
+    sessions: Mutex<HashMap<String, Conversation>>,
+    config: HistoryConfig,
+    agents: Arc<Agents>,
+}
+
+impl History {
+    pub fn new(config: HistoryConfig, agents: Arc<Agents>) -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+            config,
+            agents,
+        }
+    }
+
+    /// Renders the session's history for an agent prompt, keeping the newest
+    /// exchanges that fit the token budget. Empty when nothing was said yet.
+    pub async fn render(&self, session: &str) -> String {
+        let sessions = self.sessions.lock().await;
+        let Some(conversation) = sessions.get(session) else {
+            return String::new();
+        };
+
+        let mut budget = self.config.max_tokens;
+        let mut lines = Vec::new();
+        if let Some(summary) = &conversation.summary {
+            let line = self.agents.templates().earlier.render(&[("summary", summary.as_str())]);
+            budget = budget.saturating_sub(estimate_tokens(&line));
+            lines.push(line);
+        }
+
+        let mut recent = Vec::new();
+        for turn in conversation.turns.iter().rev() {
+            let rendered = turn.render(&self.agents.templates().turn);
+            let tokens = estimate_tokens(&rendered);
+            if tokens > budget {
+                break;
+            }
+            budget -= tokens;
+            recent.push(rendered);
+        }
+        recent.reverse();
+        lines.extend(recent);
+
+        lines.join("\n")
+    }
+
+    /// Adme's reply to the last prompt of the session, if it has one.
+    pub async fn last_reply(&self, session: &str) -> Option<String> {
+        let sessions = self.sessions.lock().await;
+        Some(sessions.get(session)?.turns.last()?.assistant.clone())
+    }
+
+    pub async fn record(&self, session: &str, user: &str, assistant: &str) {
+        let mut sessions = self.sessions.lock().await;
+        sessions.entry(session.to_string()).or_default().turns.push(Turn {
+            user: user.to_string(),
+            assistant: assistant.to_string(),
+        });
+    }
+
+    /// Folds the oldest exchanges into the session summary once the history outgrows its budget.
+    pub async fn compact(&self, session: &str) -> anyhow::Result<()> {
+        let (summary, old_turns) = {
+            let sessions = self.sessions.lock().await;
+            let Some(conversation) = sessions.get(session) else {
+                return Ok(());
+            };
+
+            let tokens = conversation
+                .turns
+                .iter()
+                .map(|turn| estimate_tokens(&turn.render(&self.agents.templates().turn)))
+                .sum::<usize>();
+            let foldable = conversation
+                .turns
+                .len()
+                .saturating_sub(self.config.keep_recent_turns);
+            if tokens <= self.config.max_tokens || foldable == 0 {
+                return Ok(());
+            }
+
+            (
+                conversation.summary.clone(),
+                conversation.turns[..foldable].to_vec(),
+            )
+        };
+
+        // Summarise without holding the lock so other sessions aren't blocked on the LLM
+        let summary_agent = self.agents.get().summariser.agent(self.agents.providers(), vec![]);
+
+        let transcript = old_turns
+            .iter()
+            .map(|turn| turn.render(&self.agents.templates().turn))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let new_summary = summary_agent
+            .prompt(&self.agents.templates().summarise.render(&[
+                ("summary", summary.as_deref().unwrap_or("(none)")),
+                ("exchanges", transcript.as_str()),
+            ]))
+            .await?;
+        let new_summary = filter_think_tag(&new_summary).trim().to_string();
+
+        let mut sessions = self.sessions.lock().await;
+        if let Some(conversation) = sessions.get_mut(session) {
+            // Another compaction may have won the race, only drop turns that are still there
+            if conversation.turns.starts_with(&old_turns) {
+                conversation.turns.drain(..old_turns.len());
+                conversation.summary = Some(new_summary);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Forgets everything said in the session.
+    pub async fn clear(&self, session: &str) {
+        self.sessions.lock().await.remove(session);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adme::mock::MockOllama;
+
+    #[tokio::test]
+    async fn test_render_keeps_newest_turns_within_budget() {
+        let mock = MockOllama::start().await;
+        let history = History::new(
+            HistoryConfig {
+                max_tokens: 10,
+                keep_recent_turns: 1,
+            },
+            mock.agents(),
+        );
+        history.record("chat", "first question", "first answer").await;
+        history.record("chat", "second", "reply").await;
+
+        let rendered = history.render("chat").await;
+        assert_eq!(rendered, "User: second\nAdme: reply");
+        assert!(history.render("other").await.is_empty());
+    }
+}
@@ -1,126 +1,854 @@
-use rig::{
-    Embed,
-    client::{CompletionClient, ProviderClient},
-    completion::Prompt,
-    embeddings::EmbeddingsBuilder,
-    providers::ollama,
-    tool::ToolDyn,
-    vector_store::{
-        VectorStoreIndex, in_memory_store::InMemoryVectorStore, request::VectorSearchRequestBuilder,
+mod consolidate;
+mod embedding;
+mod fact;
+mod index;
+mod keyword;
+mod persist;
+mod policy;
+mod query;
+mod scope;
+mod transfer;
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
     },
+    time::{Duration, Instant},
 };
+
+use chrono::{DateTime, Utc};
+use rig::{Embed, embeddings::Embedding, tool::ToolDyn};
 use serde::{Deserialize, Serialize};
-use tokio::sync::Mutex;
+use tokio::sync::{OnceCell, RwLock};
+use uuid::Uuid;
+
+use crate::{
+    adme::{agents::Agents, backend::Backend},
+    filters::filter_think_tag,
+};
+
+pub use fact::Fact;
+pub use index::ScoredMemory;
+use index::MemoryIndex;
+use persist::MemoryRecord;
+use transfer::ExportRecord;
+pub use consolidate::ConsolidationReport;
+use embedding::Embedder;
+pub use embedding::{EmbeddingConfig, EmbeddingProvider};
+pub use policy::{MemoryConfig, MergePolicy};
+pub use query::MemoryQuery;
+pub use scope::{Namespace, Scope, ScopeKind};
 
-use crate::{adme::agent::Agent, filters::filter_think_tag};
+/// Longest time retrieval bookkeeping may go without being written to disk
+/// while memories keep being retrieved.
+const ACCESS_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
 
-#[derive(Embed, Serialize, Deserialize, Clone, Default, Eq, PartialEq)]
+#[derive(Embed, Serialize, Deserialize, Clone, Default, PartialEq)]
 pub struct MyDoc {
-    id: String,
+    #[serde(default)]
+    pub id: String,
     #[embed]
     pub summary: String,
+    /// When the memory was first stored
+    #[serde(default)]
+    pub created_at: DateTime<Utc>,
+    /// When the memory was last rewritten, e.g. by a merge
+    #[serde(default)]
+    pub updated_at: DateTime<Utc>,
+    /// Where the memory came from, e.g. the agent or channel that stored it
+    #[serde(default)]
+    pub source: String,
+    /// Structured form of the summary when it is a well formed atomic truth
+    #[serde(default)]
+    pub fact: Option<Fact>,
+    /// Free form labels used to filter retrieval, e.g. a project code
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Namespace the memory is visible in
+    #[serde(default)]
+    pub scope: Scope,
+    /// How much the memory matters, between 0 and 1
+    #[serde(default = "default_importance")]
+    pub importance: f64,
+    /// When retrieval last returned this memory
+    #[serde(default)]
+    pub last_accessed_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub access_count: u32,
+    /// Earlier versions of this memory, oldest first
+    #[serde(default)]
+    pub history: Vec<MemoryVersion>,
+}
+
+fn default_importance() -> f64 {
+    MemoryConfig::default().default_importance
+}
+
+/// A memory to be stored, before it is reconciled with the rest of the store.
+pub struct NewMemory {
+    pub summary: String,
+    pub tags: Vec<String>,
+    pub scope: Scope,
+    pub source: String,
+    /// Between 0 and 1, the configured default when `None`
+    pub importance: Option<f64>,
+}
+
+/// A previous state of a memory, kept whenever it is merged or rewritten.
+#[derive(Serialize, Deserialize, Clone, Default, Eq, PartialEq)]
+pub struct MemoryVersion {
+    pub summary: String,
+    pub updated_at: DateTime<Utc>,
+    pub source: String,
+}
+
+/// What happened to a memory passed to [`Memory::store_memory`].
+pub enum StoreOutcome {
+    Added(MyDoc),
+    Merged(MyDoc),
+    /// The merge policy wants the user to decide what to do with a similar memory
+    NeedsConfirmation { existing: MyDoc },
+}
+
+impl MyDoc {
+    /// Creates a brand new memory with a fresh unique ID.
+    pub fn new(summary: String, scope: Scope, source: &str, importance: f64) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            fact: summary.parse().ok(),
+            summary,
+            created_at: now,
+            updated_at: now,
+            source: source.to_string(),
+            tags: Vec::new(),
+            scope,
+            importance,
+            last_accessed_at: None,
+            access_count: 0,
+            history: Vec::new(),
+        }
+    }
+
+    /// When the memory was last retrieved or rewritten, whichever is later.
+    pub fn last_used(&self) -> DateTime<Utc> {
+        self.last_accessed_at
+            .map_or(self.updated_at, |accessed| accessed.max(self.updated_at))
+    }
+
+    /// Adds any of `tags` the memory doesn't already carry.
+    fn add_tags(&mut self, tags: &[String]) {
+        for tag in tags {
+            if !self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+                self.tags.push(tag.clone());
+            }
+        }
+    }
+
+    /// Whether `other` is this memory with the same content, ignoring access metadata.
+    fn same_version(&self, other: &MyDoc) -> bool {
+        self.updated_at == other.updated_at && self.history.len() == other.history.len()
+    }
+
+    /// Rewrites the summary, keeping the memory's identity and recording the current version in its history.
+    fn revise(&self, summary: String, source: &str) -> Self {
+        let mut history = self.history.clone();
+        history.push(MemoryVersion {
+            summary: self.summary.clone(),
+            updated_at: self.updated_at,
+            source: self.source.clone(),
+        });
+
+        Self {
+            fact: summary.parse().ok(),
+            summary,
+            updated_at: Utc::now(),
+            source: source.to_string(),
+            history,
+            ..self.clone()
+        }
+    }
 }
 
 pub struct Memory {
-    vector_store: Mutex<InMemoryVectorStore<MyDoc>>,
+    index: RwLock<MemoryIndex>,
+    /// Loaded on first use so startup doesn't wait on the model weights
+    embedder: OnceCell<Embedder>,
+    /// File the store is written through to, `None` keeps it in memory only
+    path: Option<PathBuf>,
+    /// Held while the store is open, see [`persist::lock`]
+    _lock: Option<std::fs::File>,
+    /// Whether [`Memory::mark_accessed`] changed anything not yet on disk
+    accessed: AtomicBool,
+    /// When the store was last written
+    persisted_at: std::sync::Mutex<Instant>,
+    config: MemoryConfig,
+    /// Shared with the conversation stages so background consolidation waits its turn
+    backend: Arc<Backend>,
+    agents: Arc<Agents>,
 }
 
 impl Memory {
-    pub async fn n_closest_memories(
-        &self,
-        info: &str,
-        n: u64,
-    ) -> anyhow::Result<Vec<(f64, std::string::String, MyDoc)>> {
-        let fastembed_client = rig_fastembed::Client::new();
+    /// Opens the memory store persisted at `path`, creating an empty one if it does not exist.
+    ///
+    /// Fails if another process has the store open.
+    pub fn open(
+        path: impl Into<PathBuf>,
+        config: MemoryConfig,
+        backend: Arc<Backend>,
+        agents: Arc<Agents>,
+    ) -> anyhow::Result<Self> {
+        let path = path.into();
+        let lock = persist::lock(&path)?;
+        let index = MemoryIndex::new(persist::load(&path)?);
+        log::info!("Loaded {} memories from {}", index.len(), path.display());
 
-        let embedding_model =
-            fastembed_client.embedding_model(&rig_fastembed::FastembedModel::AllMiniLML6V2);
+        Ok(Self {
+            index: RwLock::new(index),
+            embedder: OnceCell::new(),
+            path: Some(path),
+            _lock: Some(lock),
+            accessed: AtomicBool::new(false),
+            persisted_at: std::sync::Mutex::new(Instant::now()),
+            config,
+            backend,
+            agents,
+        })
+    }
 
-        let vector_search = VectorSearchRequestBuilder::default()
-            .query(info)
-            .samples(n)
-            .build()?;
+    /// An empty store that is never written to disk.
+    #[cfg(test)]
+    pub fn in_memory(config: MemoryConfig, agents: Arc<Agents>) -> Self {
+        Self {
+            index: RwLock::new(MemoryIndex::default()),
+            embedder: OnceCell::new(),
+            path: None,
+            _lock: None,
+            accessed: AtomicBool::new(false),
+            persisted_at: std::sync::Mutex::new(Instant::now()),
+            config,
+            backend: Arc::new(Backend::default()),
+            agents,
+        }
+    }
+
+    /// Writes the whole store to disk if this memory is backed by a file.
+    async fn persist(&self, index: &MemoryIndex) -> anyhow::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        // Every write carries the access metadata along with it
+        self.accessed.store(false, Ordering::Relaxed);
+        *self.persisted_at.lock().unwrap() = Instant::now();
+        if let Err(e) = persist::save(path, index.records()).await {
+            self.accessed.store(true, Ordering::Relaxed);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Writes the access metadata [`Memory::mark_accessed`] has held back, if any.
+    pub async fn flush(&self) -> anyhow::Result<()> {
+        if !self.accessed.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let guard = self.index.read().await;
+        self.persist(&guard).await
+    }
+
+    async fn embed(&self, text: &str) -> anyhow::Result<Embedding> {
+        let embedder = self
+            .embedder
+            .get_or_try_init(|| async { Embedder::new(&self.config.embedding, self.agents.providers()) })
+            .await?;
+        embedder.embed(text).await
+    }
+
+    /// Pairs `doc` with its embedding, tagged with the current model.
+    fn record(&self, doc: MyDoc, embedding: Embedding) -> MemoryRecord {
+        MemoryRecord {
+            doc,
+            embeddings: vec![embedding],
+            embedding_model: self.config.embedding.id(),
+        }
+    }
+
+    /// Re-embeds every memory that was embedded by a different model than the configured one.
+    ///
+    /// Until this has run, such memories score 0 for similarity and are only
+    /// found by keyword.
+    pub async fn migrate_embeddings(&self) -> anyhow::Result<usize> {
+        let model = self.config.embedding.id();
+        let outdated = {
+            let guard = self.index.read().await;
+            guard
+                .records()
+                .filter(|record| record.embedding_model != model)
+                .map(|record| record.doc.clone())
+                .collect::<Vec<_>>()
+        };
+        if outdated.is_empty() {
+            return Ok(0);
+        }
 
-        let guard = self.vector_store.lock().await;
+        log::info!("Re-embedding {} memories with {}", outdated.len(), model);
+        let mut records = Vec::with_capacity(outdated.len());
+        for doc in outdated {
+            let embedding = self.embed(&doc.summary).await?;
+            records.push(self.record(doc, embedding));
+        }
 
-        let index = guard.clone().index(embedding_model.clone());
+        let count = records.len();
+        let mut guard = self.index.write().await;
+        for record in records {
+            // Skip memories deleted or rewritten while we were embedding
+            if guard.get(&record.doc.id).is_some_and(|current| current.doc == record.doc) {
+                guard.insert(record);
+            }
+        }
+        self.persist(&guard).await?;
 
-        let results = index.top_n::<MyDoc>(vector_search).await?;
+        Ok(count)
+    }
+
+    /// The configured merge policy.
+    pub fn merge_policy(&self) -> MergePolicy {
+        self.config.merge_policy
+    }
+
+    /// Finds the memories best matching `query`, blending vector similarity with keyword relevance.
+    pub async fn n_closest_memories(&self, query: &MemoryQuery) -> anyhow::Result<Vec<ScoredMemory>> {
+        let embedding = self.embed(&query.text).await?;
+
+        let guard = self.index.read().await;
+        // Ranked in full so memories below `min_score` don't use up the limit
+        let results = guard
+            .search(
+                &embedding,
+                &self.config.embedding.id(),
+                &query.text,
+                usize::MAX,
+                &self.config.ranking,
+                |doc| query.matches(doc),
+            )
+            .into_iter()
+            .filter(|result| result.relevance >= query.min_score)
+            .take(query.limit)
+            .collect();
 
         Ok(results)
     }
 
-    pub async fn store_memory(&self, mem: &str) -> anyhow::Result<()> {
-        println!("Storing memory: {}", mem);
-        let fastembed_client = rig_fastembed::Client::new();
+    /// Stores `new`, reconciling it with similar memories according to the merge policy.
+    ///
+    /// `policy` overrides the configured policy, e.g. for the extractor, which has nobody to ask.
+    pub async fn store_memory(
+        &self,
+        new: NewMemory,
+        policy: Option<MergePolicy>,
+    ) -> anyhow::Result<StoreOutcome> {
+        /// Merges tried before giving up and adding the memory alongside
+        const MAX_MERGE_ATTEMPTS: usize = 3;
 
-        let embedding_model =
-            fastembed_client.embedding_model(&rig_fastembed::FastembedModel::AllMiniLML6V2);
+        let policy = policy.unwrap_or(self.config.merge_policy);
+        let NewMemory { summary: mem, tags, scope, source, importance } = new;
+        let importance = importance.unwrap_or(self.config.default_importance).clamp(0.0, 1.0);
+        log::debug!("Storing memory in {} from {} ({:?}): {}", scope, source, policy, mem);
 
-        
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            // Memories are only ever reconciled with others in the same scope
+            let existing = if attempts > MAX_MERGE_ATTEMPTS {
+                None
+            } else {
+                match mem.parse::<Fact>() {
+                    // The same subject and attribute is matched deterministically
+                    Ok(fact) => self.find_fact(&fact, &scope).await,
+                    Err(e) => {
+                        log::debug!("Not an atomic truth ({}), matching by similarity", e);
+                        self.find_similar(&mem, &scope).await?
+                    }
+                }
+            };
 
-        let id: String;
+            let (mut doc, merged) = match (&existing, policy) {
+                (None, _) | (Some(_), MergePolicy::AlwaysAppend) => {
+                    (MyDoc::new(mem.clone(), scope.clone(), &source, importance), false)
+                }
+                (Some(existing), MergePolicy::AskUser) => {
+                    return Ok(StoreOutcome::NeedsConfirmation {
+                        existing: existing.clone(),
+                    });
+                }
+                (Some(existing), MergePolicy::Merge) => {
+                    let mut doc = self.merge_into(existing, &mem, &source).await?;
+                    doc.importance = doc.importance.max(importance);
+                    (doc, true)
+                }
+            };
+            doc.add_tags(&tags);
 
-        let results = self.n_closest_memories(mem, 1).await?;
+            let embedding = self.embed(&doc.summary).await?;
 
-        //Get the id to write into
-        const SIM_THRESHOLD: f64 = 0.85;
+            let mut guard = self.index.write().await;
+            // Another store, update or forget may have changed the memory while
+            // it was being merged, and writing the merge would undo that
+            if merged
+                && let Some(existing) = &existing
+                && !guard.get(&existing.id).is_some_and(|current| current.doc.same_version(existing))
+            {
+                log::info!("Memory {} changed during the merge, trying again", existing.id);
+                continue;
+            }
+            guard.insert(self.record(doc.clone(), embedding));
+            self.persist(&guard).await?;
 
-        let mut comb_mem;
-        let mut guard = self.vector_store.lock().await;
-        if !results.is_empty() && results[0].0 > SIM_THRESHOLD {
-            let top = &results[0];
-            println!("SIMILARITY IS: {}", top.0);
-            println!("Replacing: {}", top.2.summary);
-            id = top.1.clone();
-            let combine_agent = ollama::Client::from_env().agent("qwen3:30b").preamble("Role: You are looking at a new memory and one of your current memories.\nTask: combine them into a single, concise memory that captures all essential information from both.\nInstructions:\n1. If you find any information conflicting, go with the more up to date info\n2. If there is little to nothing to gain by updating the same don't bother updating it").build();
+            return Ok(if merged {
+                StoreOutcome::Merged(doc)
+            } else {
+                StoreOutcome::Added(doc)
+            });
+        }
+    }
+
+    /// Finds the fact in `scope` with the same subject and attribute as `fact`.
+    async fn find_fact(&self, fact: &Fact, scope: &Scope) -> Option<MyDoc> {
+        let guard = self.index.read().await;
+        guard
+            .records()
+            .filter(|record| &record.doc.scope == scope)
+            .find(|record| record.doc.fact.as_ref().is_some_and(|f| f.same_key(fact)))
+            .map(|record| record.doc.clone())
+    }
+
+    /// Finds the memory in `scope` most similar to `mem`, if it is above the similarity threshold.
+    async fn find_similar(&self, mem: &str, scope: &Scope) -> anyhow::Result<Option<MyDoc>> {
+        let embedding = self.embed(mem).await?;
 
-            comb_mem = combine_agent
-                .prompt(format!(
-                    "New memory: {}\nCurrent memory: {}",
-                    mem, top.2.summary
-                ))
-                .await?;
+        // Merging compares meaning only, so keyword relevance is left out here
+        let guard = self.index.read().await;
+        let Some((score, doc)) = guard.top_n(&embedding, &self.config.embedding.id(), 1, |doc| &doc.scope == scope).into_iter().next() else {
+            return Ok(None);
+        };
+        if score <= self.config.similarity_threshold {
+            return Ok(None);
+        }
+
+        log::debug!("Memory {} is similar at {:.3}", doc.id, score);
+        Ok(Some(doc))
+    }
 
-            comb_mem = filter_think_tag(&comb_mem);
-            println!("with: {}", comb_mem);
-        } else {
-            comb_mem = mem.to_string();
-            println!("Adding: {}", comb_mem);
-            id = guard.len().to_string();
+    /// Combines `mem` into `existing`, keeping the existing memory's identity.
+    async fn merge_into(&self, existing: &MyDoc, mem: &str, source: &str) -> anyhow::Result<MyDoc> {
+        // Facts on the same subject and attribute are superseded outright
+        if let (Ok(fact), Some(old)) = (mem.parse::<Fact>(), &existing.fact)
+            && old.same_key(&fact)
+        {
+            if old.contradicts(&fact) {
+                log::info!("Contradiction on {} {}", fact.subject, fact.attribute);
+            }
+            return Ok(existing.revise(fact.to_string(), source));
         }
 
-        let embeddings = EmbeddingsBuilder::new(embedding_model.clone())
-            .document(MyDoc {
-                id,
-                summary: comb_mem,
-            })?
-            .build()
+        let combine_agent = self.agents.get().combiner.agent(self.agents.providers(), vec![]);
+
+        let comb_mem = combine_agent
+            .prompt(&self.agents.templates().combine.render(&[
+                ("new_memory", mem),
+                ("current_memory", existing.summary.as_str()),
+            ]))
             .await?;
 
-        guard.add_documents_with_id_f(embeddings, |d| d.id.clone());
+        let comb_mem = filter_think_tag(&comb_mem);
 
-        println!("CURRENTLY {} MEMORIES IN STORAGE", guard.len());
+        Ok(existing.revise(comb_mem, source))
+    }
 
-        Ok(())
+    /// Records that retrieval returned these memories, reinforcing their importance.
+    ///
+    /// Rewriting the whole store on every retrieval would hold up everything
+    /// else waiting on the index, so the change is only written if the store
+    /// hasn't been for [`ACCESS_FLUSH_INTERVAL`]. Anything held back goes out
+    /// with the next write or [`Memory::flush`].
+    pub async fn mark_accessed(&self, ids: &[String]) -> anyhow::Result<()> {
+        /// Importance gained each time a memory turns out to be useful
+        const ACCESS_BOOST: f64 = 0.05;
+
+        let now = Utc::now();
+        let mut guard = self.index.write().await;
+        for id in ids {
+            if let Some(record) = guard.get_mut(id) {
+                record.doc.last_accessed_at = Some(now);
+                record.doc.access_count += 1;
+                record.doc.importance = (record.doc.importance + ACCESS_BOOST).min(1.0);
+                self.accessed.store(true, Ordering::Relaxed);
+            }
+        }
+
+        if self.persisted_at.lock().unwrap().elapsed() < ACCESS_FLUSH_INTERVAL {
+            return Ok(());
+        }
+        self.persist(&guard.downgrade()).await
+    }
+
+    /// Returns every stored memory, most recently updated first.
+    pub async fn list_memories(&self) -> Vec<MyDoc> {
+        let guard = self.index.read().await;
+        let mut docs = guard
+            .records()
+            .map(|record| record.doc.clone())
+            .collect::<Vec<_>>();
+        docs.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
+        docs
+    }
+
+    /// Looks up a single memory by its ID.
+    pub async fn get_memory(&self, id: &str) -> Option<MyDoc> {
+        let guard = self.index.read().await;
+        guard.get(id).map(|record| record.doc.clone())
+    }
+
+    /// Replaces the content of an existing memory, keeping its ID and creation time.
+    pub async fn update_memory(&self, id: &str, summary: &str, source: &str) -> anyhow::Result<MyDoc> {
+        let embedding = self.embed(summary).await?;
+
+        let mut guard = self.index.write().await;
+        let Some(current) = guard.get(id) else {
+            anyhow::bail!("No memory with id {}", id);
+        };
+
+        let doc = current.doc.revise(summary.to_string(), source);
+        log::info!("Updating memory {}: {}", id, doc.summary);
+
+        guard.insert(self.record(doc.clone(), embedding));
+        self.persist(&guard).await?;
+
+        Ok(doc)
+    }
+
+    /// Removes a memory from the store, returning what was forgotten.
+    pub async fn delete_memory(&self, id: &str) -> anyhow::Result<MyDoc> {
+        let mut guard = self.index.write().await;
+        let Some(removed) = guard.remove(id) else {
+            anyhow::bail!("No memory with id {}", id);
+        };
+        self.persist(&guard).await?;
+
+        log::info!("Forgot memory {}: {}", id, removed.doc.summary);
+        Ok(removed.doc)
+    }
+
+    /// Restores a memory to the version at `version` in its history.
+    ///
+    /// The version being replaced is itself kept in the history, so a rollback can be undone.
+    pub async fn rollback_memory(&self, id: &str, version: usize, source: &str) -> anyhow::Result<MyDoc> {
+        let Some(current) = self.get_memory(id).await else {
+            anyhow::bail!("No memory with id {}", id);
+        };
+        let Some(previous) = current.history.get(version) else {
+            anyhow::bail!("Memory {} has no version {}", id, version);
+        };
+
+        log::info!("Rolling back memory {} to version {}", id, version);
+        self.update_memory(id, &previous.summary, source).await
+    }
+
+    /// Writes every memory to `path` as JSONL, returning how many were exported.
+    pub async fn export_jsonl(&self, path: &Path, include_embeddings: bool) -> anyhow::Result<usize> {
+        let records = {
+            let guard = self.index.read().await;
+            guard
+                .records()
+                .map(|record| ExportRecord {
+                    doc: record.doc.clone(),
+                    embedding_model: include_embeddings.then(|| record.embedding_model.clone()),
+                    embeddings: include_embeddings.then(|| record.embeddings.clone()),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        transfer::write(path, &records).await?;
+        log::info!("Exported {} memories to {}", records.len(), path.display());
+        Ok(records.len())
+    }
+
+    /// Loads memories from a JSONL export, replacing any with the same ID.
+    ///
+    /// Embeddings are reused only when they came from the current model, otherwise
+    /// the memory is re-embedded. Returns how many memories were imported.
+    pub async fn import_jsonl(&self, path: &Path) -> anyhow::Result<usize> {
+        let mut records = Vec::new();
+        for export in transfer::read(path).await? {
+            // Hand written entries may only carry a summary
+            let mut doc = export.doc;
+            if doc.id.is_empty() {
+                doc.id = Uuid::new_v4().to_string();
+            }
+            if doc.created_at == DateTime::<Utc>::default() {
+                doc.created_at = Utc::now();
+                doc.updated_at = doc.created_at;
+            }
+            if doc.fact.is_none() {
+                doc.fact = doc.summary.parse().ok();
+            }
+
+            let record = match (export.embedding_model, export.embeddings) {
+                (Some(model), Some(embeddings))
+                    if model == self.config.embedding.id() && !embeddings.is_empty() =>
+                {
+                    MemoryRecord {
+                        doc,
+                        embeddings,
+                        embedding_model: model,
+                    }
+                }
+                _ => {
+                    let embedding = self.embed(&doc.summary).await?;
+                    self.record(doc, embedding)
+                }
+            };
+            records.push(record);
+        }
+
+        let count = records.len();
+        let mut guard = self.index.write().await;
+        for record in records {
+            guard.insert(record);
+        }
+        self.persist(&guard).await?;
+
+        log::info!("Imported {} memories from {}", count, path.display());
+        Ok(count)
+    }
+
+    /// Has the extractor agent store what it learns from `input` with `tools`,
+    /// returning its summary of what it learned, or `None` if nothing.
+    pub async fn extract(&self, input: &str, tools: Vec<Box<dyn ToolDyn>>) -> anyhow::Result<Option<String>> {
+        let memory_agent = self.agents.get().extractor.agent(self.agents.providers(), tools);
+
+        let summary = filter_think_tag(&memory_agent.prompt(input).await?);
+        let summary = summary.trim();
+        if summary.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(summary.to_string()))
     }
 }
 
-impl Agent for Memory {
-    fn new() -> Self {
-        Self {
-            vector_store: Mutex::new(InMemoryVectorStore::default()),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adme::{
+        mock::{MockOllama, MockReply},
+        tools::StoreMemory,
+    };
+
+    fn new_memory(summary: &str) -> NewMemory {
+        NewMemory {
+            summary: summary.to_string(),
+            tags: vec![],
+            scope: Scope::Global,
+            source: String::from("test"),
+            importance: None,
         }
     }
 
-    async fn prompt(&self, input: &str, tools: Vec<Box<dyn ToolDyn>>) -> anyhow::Result<Option<String>> {
-        let memory_agent = ollama::Client::from_env().agent("qwen3:30b")
-        .preamble(
-            "Role: You are a Memory Architect for a Sovereign AI.\nTask: Analyze the provided conversation and extract discrete, high signal \"Atomic Truths\". Call store_store memory with these truths.\nInstructions:\n1. Ignore filler, politeness, and temporary statements.\n2. Format each truth strictly as: Subject | Attribute | Value | Context | Rationale.\n3. Rationale must explain **why** this was concluded (e.g., \"User explicitly stated,\" or \"Inferred from repeated code patterns\").\n4. If a new truth contracdicts an old one, note it in the Context.\n5. Resolve all pronouns. Replace 'I/Me/My' with 'User' and 'You/Your' with 'Assistant'. Every truth must be an objective statement about a specific entity.\nExample\nInput:\"Actually, let's switch the 3D renderer to Vulkan. OpenGL is too slow for this geometry kernel.\"\nOutput:3D renderer | technology | Vulkan | Project Kernel Development | Switched from OpenGL due to performance bottlenecks in geometry processing.")
-            .tools(tools)
-            .build();
+    #[tokio::test]
+    async fn test_similar_memories_are_combined() {
+        let mock = MockOllama::start().await;
+        mock.expect(
+            "Current memory: User prefers dark mode in the editor",
+            MockReply::text("User prefers dark mode in every code editor"),
+        );
+        let memory = Memory::in_memory(MockOllama::memory_config(), mock.agents());
+
+        memory.store_memory(new_memory("User prefers dark mode in the editor"), None).await.unwrap();
+        let outcome = memory
+            .store_memory(new_memory("User prefers dark mode in the code editor"), None)
+            .await
+            .unwrap();
+
+        let StoreOutcome::Merged(doc) = outcome else {
+            panic!("expected the memories to be merged");
+        };
+        assert_eq!(doc.summary, "User prefers dark mode in every code editor");
+        assert_eq!(doc.history.len(), 1);
+        assert_eq!(memory.list_memories().await.len(), 1);
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_facts_are_superseded_without_asking_the_llm() {
+        let mock = MockOllama::start().await;
+        let memory = Memory::in_memory(MockOllama::memory_config(), mock.agents());
+
+        memory
+            .store_memory(new_memory("Renderer | technology | OpenGL | Kernel | User explicitly stated"), None)
+            .await
+            .unwrap();
+        let outcome = memory
+            .store_memory(new_memory("Renderer | technology | Vulkan | Kernel | User explicitly stated"), None)
+            .await
+            .unwrap();
+
+        let StoreOutcome::Merged(doc) = outcome else {
+            panic!("expected the fact to be superseded");
+        };
+        assert!(doc.summary.contains("Vulkan"));
+        assert!(mock.chat_requests().is_empty());
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_always_append_keeps_similar_memories_apart() {
+        let mock = MockOllama::start().await;
+        let config = MemoryConfig {
+            merge_policy: MergePolicy::AlwaysAppend,
+            ..MockOllama::memory_config()
+        };
+        let memory = Memory::in_memory(config, mock.agents());
+
+        memory.store_memory(new_memory("User prefers dark mode in the editor"), None).await.unwrap();
+        let outcome = memory
+            .store_memory(new_memory("User prefers dark mode in the code editor"), None)
+            .await
+            .unwrap();
+
+        assert!(matches!(outcome, StoreOutcome::Added(_)));
+        assert_eq!(memory.list_memories().await.len(), 2);
+        assert!(mock.chat_requests().is_empty());
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_ask_user_waits_for_confirmation_before_merging() {
+        let mock = MockOllama::start().await;
+        mock.expect(
+            "Current memory: User prefers dark mode in the editor",
+            MockReply::text("User prefers dark mode in every code editor"),
+        );
+        let config = MemoryConfig {
+            merge_policy: MergePolicy::AskUser,
+            ..MockOllama::memory_config()
+        };
+        let memory = Memory::in_memory(config, mock.agents());
+
+        memory.store_memory(new_memory("User prefers dark mode in the editor"), None).await.unwrap();
+        let outcome = memory
+            .store_memory(new_memory("User prefers dark mode in the code editor"), None)
+            .await
+            .unwrap();
+        let StoreOutcome::NeedsConfirmation { existing } = outcome else {
+            panic!("expected to be asked before merging");
+        };
+        assert_eq!(existing.summary, "User prefers dark mode in the editor");
+        assert_eq!(memory.list_memories().await.len(), 1);
+
+        // What the extractor does, having nobody to ask
+        let outcome = memory
+            .store_memory(new_memory("User prefers dark mode in the code editor"), Some(MergePolicy::Merge))
+            .await
+            .unwrap();
+        assert!(matches!(outcome, StoreOutcome::Merged(doc) if doc.id == existing.id));
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_the_extractor_keeps_always_append() {
+        let mock = MockOllama::start().await;
+        mock.reply(MockReply::tool_call(
+            "store_memory",
+            serde_json::json!({"info": "User prefers dark mode in the code editor"}),
+        ))
+        .reply(MockReply::text("User prefers dark mode in the code editor"));
+        let config = MemoryConfig {
+            merge_policy: MergePolicy::AlwaysAppend,
+            ..MockOllama::memory_config()
+        };
+        let memory = Arc::new(Memory::in_memory(config, mock.agents()));
+        memory.store_memory(new_memory("User prefers dark mode in the editor"), None).await.unwrap();
+
+        // As built by `extract_memories`
+        let tools: Vec<Box<dyn ToolDyn>> = vec![Box::new(StoreMemory {
+            memory: memory.clone(),
+            source: String::from("extractor"),
+            namespace: Namespace::default(),
+            policy: Some(memory.merge_policy().unattended()),
+        })];
+        memory.extract("User Prompt: I use dark mode in every editor", tools).await.unwrap();
+
+        assert_eq!(memory.list_memories().await.len(), 2);
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_only_ask_user_changes_when_unattended() {
+        assert_eq!(MergePolicy::AskUser.unattended(), MergePolicy::Merge);
+        assert_eq!(MergePolicy::AlwaysAppend.unattended(), MergePolicy::AlwaysAppend);
+        assert_eq!(MergePolicy::Merge.unattended(), MergePolicy::Merge);
+    }
+
+    #[tokio::test]
+    async fn test_rollback_restores_an_earlier_version() {
+        let mock = MockOllama::start().await;
+        let memory = Memory::in_memory(MockOllama::memory_config(), mock.agents());
+
+        memory
+            .store_memory(new_memory("Renderer | technology | OpenGL | Kernel | User explicitly stated"), None)
+            .await
+            .unwrap();
+        let StoreOutcome::Merged(doc) = memory
+            .store_memory(new_memory("Renderer | technology | Vulkan | Kernel | User explicitly stated"), None)
+            .await
+            .unwrap()
+        else {
+            panic!("expected the fact to be superseded");
+        };
+
+        let restored = memory.rollback_memory(&doc.id, 0, "test").await.unwrap();
+        assert!(restored.summary.contains("OpenGL"));
+        // The rolled back version is kept so the rollback can be undone
+        assert_eq!(restored.history.len(), 2);
+        assert!(restored.history[1].summary.contains("Vulkan"));
+        assert!(memory.rollback_memory(&doc.id, 5, "test").await.is_err());
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_export_then_import_restores_every_memory() {
+        let mock = MockOllama::start().await;
+        let memory = Memory::in_memory(MockOllama::memory_config(), mock.agents());
+        memory.store_memory(new_memory("User prefers dark mode in the editor"), None).await.unwrap();
+        memory
+            .store_memory(new_memory("Renderer | technology | Vulkan | Kernel | User explicitly stated"), None)
+            .await
+            .unwrap();
+
+        let path = std::env::temp_dir().join(format!("adme-export-{}.jsonl", std::process::id()));
+        assert_eq!(memory.export_jsonl(&path, true).await.unwrap(), 2);
+        let imported = Memory::in_memory(MockOllama::memory_config(), mock.agents());
+        assert_eq!(imported.import_jsonl(&path).await.unwrap(), 2);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(imported.list_memories().await == memory.list_memories().await);
+        mock.assert_done();
+    }
+
+    #[tokio::test]
+    async fn test_a_store_is_only_opened_once() {
+        let mock = MockOllama::start().await;
+        let path = std::env::temp_dir().join(format!("adme-lock-{}.jsonl", std::process::id()));
+        let open = || Memory::open(&path, MockOllama::memory_config(), Arc::new(Backend::default()), mock.agents());
+
+        let first = open().unwrap();
+        let error = open().err().expect("the store is already open");
+        assert!(error.to_string().contains("in use"), "{error}");
 
-        let mut summary = filter_think_tag(&memory_agent.prompt(input).await?);
-        Ok(None)
+        drop(first);
+        assert!(open().is_ok());
+        let _ = std::fs::remove_file(path.with_extension("jsonl.lock"));
     }
 }
@@ -0,0 +1,177 @@
+//! Periodic upkeep of the memory store.
+//!
+//! Near-duplicate memories are folded together and memories that have gone
+//! unused for a long time are summarised into a single digest per scope, so
+//! the store stays useful after months of daily use.
+
+use std::{collections::HashMap, sync::Arc};
+
+use chrono::Utc;
+use serde::Serialize;
+
+use crate::{
+    adme::memory::{Memory, MemoryVersion, MyDoc, Scope, index::MemoryIndex},
+    filters::filter_think_tag,
+};
+
+/// Recorded as the source of memories rewritten by consolidation.
+const SOURCE: &str = "consolidation";
+
+#[derive(Serialize, Default, Debug)]
+pub struct ConsolidationReport {
+    /// Memories folded into another near-duplicate
+    pub merged: usize,
+    /// Stale memories replaced by a digest
+    pub summarised: usize,
+}
+
+impl Memory {
+    /// Runs [`Memory::consolidate`] forever at the configured interval.
+    pub async fn consolidate_periodically(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(self.config.consolidation.interval);
+        // The first tick completes immediately, skip it so startup isn't slowed down
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+            match self.consolidate().await {
+                Ok(report) => log::info!(
+                    "Memory consolidation merged {} and summarised {} memories",
+                    report.merged, report.summarised
+                ),
+                Err(e) => log::warn!("Memory consolidation failed: {:#}", e),
+            }
+        }
+    }
+
+    pub async fn consolidate(&self) -> anyhow::Result<ConsolidationReport> {
+        let _permit = self.backend.permit().await;
+        Ok(ConsolidationReport {
+            merged: self.merge_duplicates().await?,
+            summarised: self.summarise_stale().await?,
+        })
+    }
+
+    /// Folds every cluster of near-duplicates into its oldest member.
+    async fn merge_duplicates(&self) -> anyhow::Result<usize> {
+        let clusters = {
+            let guard = self.index.read().await;
+            guard.duplicate_clusters(self.config.similarity_threshold)
+        };
+
+        let mut merged = 0;
+        for cluster in clusters {
+            // Newer members are folded in last so their information wins conflicts
+            let (first, rest) = cluster.split_first().expect("clusters have at least two members");
+            let mut survivor = first.clone();
+            for other in rest {
+                survivor = self
+                    .backend
+                    .deadline(self.merge_into(&survivor, &other.summary, SOURCE))
+                    .await?;
+                survivor.importance = survivor.importance.max(other.importance);
+                survivor.access_count += other.access_count;
+                survivor.add_tags(&other.tags);
+            }
+
+            let embedding = self.embed(&survivor.summary).await?;
+
+            let mut guard = self.index.write().await;
+            if !unchanged(&guard, &cluster) {
+                log::info!("Skipping a merge, its memories changed while it was written");
+                continue;
+            }
+            for other in rest {
+                guard.remove(&other.id);
+            }
+            guard.insert(self.record(survivor, embedding));
+            self.persist(&guard).await?;
+
+            merged += rest.len();
+        }
+
+        Ok(merged)
+    }
+
+    /// Replaces unused, unimportant memories with one digest per scope.
+    async fn summarise_stale(&self) -> anyhow::Result<usize> {
+        let config = &self.config.consolidation;
+        let cutoff = Utc::now() - chrono::Duration::days(config.stale_after_days);
+
+        let mut stale: HashMap<Scope, Vec<MyDoc>> = HashMap::new();
+        {
+            let guard = self.index.read().await;
+            for record in guard.records() {
+                let doc = &record.doc;
+                if doc.last_used() < cutoff && doc.importance < config.stale_importance {
+                    stale.entry(doc.scope.clone()).or_default().push(doc.clone());
+                }
+            }
+        }
+
+        let mut summarised = 0;
+        for (scope, docs) in stale {
+            // A single stale memory is already as compact as it gets
+            if docs.len() < 2 {
+                continue;
+            }
+
+            let digest = self.backend.deadline(self.summarise(&scope, &docs)).await?;
+            let embedding = self.embed(&digest.summary).await?;
+
+            let mut guard = self.index.write().await;
+            if !unchanged(&guard, &docs) {
+                log::info!("Skipping the digest for {}, its memories changed while it was written", scope);
+                continue;
+            }
+            for doc in &docs {
+                guard.remove(&doc.id);
+            }
+            guard.insert(self.record(digest, embedding));
+            self.persist(&guard).await?;
+
+            summarised += docs.len();
+        }
+
+        Ok(summarised)
+    }
+
+    /// Asks the LLM for a digest of `docs`, keeping each original as a version in its history.
+    async fn summarise(&self, scope: &Scope, docs: &[MyDoc]) -> anyhow::Result<MyDoc> {
+        let summary_agent = self.agents.get().consolidator.agent(self.agents.providers(), vec![]);
+
+        let memories = docs
+            .iter()
+            .map(|doc| format!("- {}", doc.summary))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let prompt = self.agents.templates().consolidate.render(&[("memories", memories.as_str())]);
+        let summary = filter_think_tag(&summary_agent.prompt(&prompt).await?);
+        if summary.trim().is_empty() {
+            anyhow::bail!("the consolidator returned an empty digest for {} memories in {}", docs.len(), scope);
+        }
+        log::debug!("Summarised {} stale memories in {}: {}", docs.len(), scope, summary);
+
+        let importance = docs.iter().map(|doc| doc.importance).fold(0.0, f64::max);
+        let mut digest = MyDoc::new(summary.trim().to_string(), scope.clone(), SOURCE, importance);
+        for doc in docs {
+            digest.add_tags(&doc.tags);
+            digest.history.push(MemoryVersion {
+                summary: doc.summary.clone(),
+                updated_at: doc.updated_at,
+                source: doc.source.clone(),
+            });
+        }
+
+        Ok(digest)
+    }
+}
+
+/// Whether every one of `docs` is still in the index as it was when read.
+///
+/// Memories can be updated, forgotten or retrieved while the LLM is busy, and
+/// replacing them then would throw that away.
+fn unchanged(index: &MemoryIndex, docs: &[MyDoc]) -> bool {
+    docs.iter()
+        .all(|doc| index.get(&doc.id).is_some_and(|current| current.doc == *doc))
+}
@@ -0,0 +1,109 @@
+//! Embedding backends for the memory store.
+//!
+//! Memories can be embedded locally with any supported fastembed model, or by
+//! the Ollama server `ProcessManager::start_ollama` launches. Every stored
+//! embedding records the model that produced it so a change of model can be
+//! detected and the store re-embedded.
+
+use std::{fmt, str::FromStr};
+
+use rig::{
+    client::EmbeddingsClient,
+    embeddings::{Embedding, EmbeddingModel as _},
+    providers::ollama,
+};
+
+use crate::adme::provider::Providers;
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum EmbeddingProvider {
+    /// Runs an ONNX model in process
+    #[default]
+    Fastembed,
+    /// Uses the embeddings endpoint of the Ollama server at `OLLAMA_API_BASE_URL`
+    Ollama,
+}
+
+impl fmt::Display for EmbeddingProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmbeddingProvider::Fastembed => write!(f, "fastembed"),
+            EmbeddingProvider::Ollama => write!(f, "ollama"),
+        }
+    }
+}
+
+impl FromStr for EmbeddingProvider {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "fastembed" => Ok(Self::Fastembed),
+            "ollama" => Ok(Self::Ollama),
+            other => anyhow::bail!("Unknown embedding provider: {}", other),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct EmbeddingConfig {
+    pub provider: EmbeddingProvider,
+    /// Model name as the provider knows it, e.g. `bge-small-en-v1.5` or `nomic-embed-text`
+    pub model: String,
+}
+
+impl Default for EmbeddingConfig {
+    fn default() -> Self {
+        Self {
+            provider: EmbeddingProvider::Fastembed,
+            model: String::from("all-minilm-l6-v2"),
+        }
+    }
+}
+
+impl EmbeddingConfig {
+    /// Identifies the model across providers, recorded with every embedding it produces.
+    pub fn id(&self) -> String {
+        format!("{}/{}", self.provider, self.model)
+    }
+}
+
+pub enum Embedder {
+    Fastembed(rig_fastembed::EmbeddingModel),
+    Ollama(ollama::EmbeddingModel),
+}
+
+impl Embedder {
+    /// Loads the model described by `config`.
+    pub fn new(config: &EmbeddingConfig, providers: &Providers) -> anyhow::Result<Self> {
+        match config.provider {
+            EmbeddingProvider::Fastembed => {
+                let model = fastembed_model(&config.model)?;
+                let fastembed_client = rig_fastembed::Client::new();
+                Ok(Self::Fastembed(fastembed_client.embedding_model(&model)))
+            }
+            EmbeddingProvider::Ollama => Ok(Self::Ollama(providers.ollama.embedding_model(&config.model))),
+        }
+    }
+
+    pub async fn embed(&self, text: &str) -> anyhow::Result<Embedding> {
+        Ok(match self {
+            Embedder::Fastembed(model) => model.embed_text(text).await?,
+            Embedder::Ollama(model) => model.embed_text(text).await?,
+        })
+    }
+}
+
+fn fastembed_model(name: &str) -> anyhow::Result<rig_fastembed::FastembedModel> {
+    use rig_fastembed::FastembedModel;
+
+    Ok(match name.to_lowercase().as_str() {
+        "all-minilm-l6-v2" => FastembedModel::AllMiniLML6V2,
+        "bge-small-en-v1.5" => FastembedModel::BGESmallENV15,
+        "bge-base-en-v1.5" => FastembedModel::BGEBaseENV15,
+        "bge-large-en-v1.5" => FastembedModel::BGELargeENV15,
+        "nomic-embed-text-v1.5" => FastembedModel::NomicEmbedTextV15,
+        "multilingual-e5-small" => FastembedModel::MultilingualE5Small,
+        other => anyhow::bail!("Unsupported fastembed model: {}", other),
+    })
+}
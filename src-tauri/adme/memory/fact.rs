@@ -0,0 +1,133 @@
+//! Typed "atomic truth" records.
+//!
+//! The memory agents are asked to write facts as
+//! `Subject | Attribute | Value | Context | Rationale`. Parsing them into
+//! fields lets the store match facts on subject and attribute directly instead
+//! of relying on embedding similarity alone.
+
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// Number of `|` separated fields in an atomic truth.
+const FIELD_COUNT: usize = 5;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Fact {
+    pub subject: String,
+    pub attribute: String,
+    pub value: String,
+    pub context: String,
+    pub rationale: String,
+}
+
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum FactParseError {
+    #[error("expected {FIELD_COUNT} '|' separated fields, found {0}")]
+    FieldCount(usize),
+    #[error("the {0} field must not be empty")]
+    EmptyField(&'static str),
+}
+
+impl Fact {
+    /// Whether `other` states something about the same subject and attribute.
+    pub fn same_key(&self, other: &Fact) -> bool {
+        normalize(&self.subject) == normalize(&other.subject)
+            && normalize(&self.attribute) == normalize(&other.attribute)
+    }
+
+    /// Whether this fact is about `subject`, ignoring case and spacing.
+    pub fn is_about(&self, subject: &str) -> bool {
+        normalize(&self.subject) == normalize(subject)
+    }
+
+    /// Whether `other` gives a different value for the same subject and attribute.
+    pub fn contradicts(&self, other: &Fact) -> bool {
+        self.same_key(other) && normalize(&self.value) != normalize(&other.value)
+    }
+}
+
+impl FromStr for Fact {
+    type Err = FactParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields = s.trim().split('|').map(str::trim).collect::<Vec<_>>();
+        if fields.len() != FIELD_COUNT {
+            return Err(FactParseError::FieldCount(fields.len()));
+        }
+
+        // Context and rationale are descriptive, the rest identify the fact
+        for (name, field) in ["subject", "attribute", "value"].into_iter().zip(&fields) {
+            if field.is_empty() {
+                return Err(FactParseError::EmptyField(name));
+            }
+        }
+
+        Ok(Self {
+            subject: fields[0].to_string(),
+            attribute: fields[1].to_string(),
+            value: fields[2].to_string(),
+            context: fields[3].to_string(),
+            rationale: fields[4].to_string(),
+        })
+    }
+}
+
+impl std::fmt::Display for Fact {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} | {} | {} | {} | {}",
+            self.subject, self.attribute, self.value, self.context, self.rationale
+        )
+    }
+}
+
+fn normalize(field: &str) -> String {
+    field
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_atomic_truth() {
+        let fact: Fact = "3D renderer | technology | Vulkan | Project Kernel Development | Switched from OpenGL"
+            .parse()
+            .unwrap();
+
+        assert_eq!(fact.subject, "3D renderer");
+        assert_eq!(fact.attribute, "technology");
+        assert_eq!(fact.value, "Vulkan");
+        assert_eq!(fact.context, "Project Kernel Development");
+        assert_eq!(fact.rationale, "Switched from OpenGL");
+    }
+
+    #[test]
+    fn test_parse_rejects_malformed_lines() {
+        assert_eq!(
+            "User likes tea".parse::<Fact>(),
+            Err(FactParseError::FieldCount(1))
+        );
+        assert_eq!(
+            "User | | tea | | stated".parse::<Fact>(),
+            Err(FactParseError::EmptyField("attribute"))
+        );
+    }
+
+    #[test]
+    fn test_contradiction_ignores_case_and_spacing() {
+        let old: Fact = "User | favourite  drink | Tea | | User stated".parse().unwrap();
+        let same: Fact = "user | Favourite drink | tea | chat | Repeated".parse().unwrap();
+        let new: Fact = "User | favourite drink | Coffee | | User stated".parse().unwrap();
+
+        assert!(!old.contradicts(&same));
+        assert!(old.contradicts(&new));
+        assert!(old.is_about(" user "));
+    }
+}
@@ -0,0 +1,363 @@
+//! Similarity index over stored memories.
+//!
+//! Keeps every memory with its embeddings in process so lookups only embed the
+//! query, and inserts or removals update the index in place. A keyword index
+//! is maintained alongside for hybrid search.
+
+use std::collections::HashMap;
+
+use chrono::Utc;
+use rig::embeddings::Embedding;
+use serde::Serialize;
+
+use crate::adme::memory::{
+    MyDoc, keyword::KeywordIndex, persist::MemoryRecord, policy::RankingConfig,
+};
+
+/// Share of the hybrid score that comes from vector similarity, the rest is keyword relevance
+const VECTOR_WEIGHT: f64 = 0.7;
+
+/// A memory returned by hybrid search with the scores that ranked it.
+#[derive(Serialize, Clone)]
+pub struct ScoredMemory {
+    /// Relevance blended with recency and importance, between 0 and 1
+    pub score: f64,
+    /// Vector similarity blended with keyword relevance, between 0 and 1
+    pub relevance: f64,
+    pub vector_score: f64,
+    /// BM25 score normalised against the best keyword match the filter let through
+    pub keyword_score: f64,
+    /// Decays from 1 as the memory goes unused
+    pub recency_score: f64,
+    pub doc: MyDoc,
+}
+
+#[derive(Default)]
+pub struct MemoryIndex {
+    records: HashMap<String, MemoryRecord>,
+    keywords: KeywordIndex,
+}
+
+impl MemoryIndex {
+    pub fn new(records: Vec<MemoryRecord>) -> Self {
+        let mut index = Self::default();
+        for record in records {
+            index.insert(record);
+        }
+        index
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&MemoryRecord> {
+        self.records.get(id)
+    }
+
+    /// Mutable access for metadata updates. The summary must not be changed
+    /// here since the keyword index would go stale, use [`MemoryIndex::insert`] instead.
+    pub fn get_mut(&mut self, id: &str) -> Option<&mut MemoryRecord> {
+        self.records.get_mut(id)
+    }
+
+    pub fn records(&self) -> impl Iterator<Item = &MemoryRecord> {
+        self.records.values()
+    }
+
+    /// Adds a memory, replacing any existing memory with the same ID.
+    pub fn insert(&mut self, record: MemoryRecord) {
+        self.keywords.insert(&record.doc.id, &record.doc.summary);
+        self.records.insert(record.doc.id.clone(), record);
+    }
+
+    pub fn remove(&mut self, id: &str) -> Option<MemoryRecord> {
+        self.keywords.remove(id);
+        self.records.remove(id)
+    }
+
+    /// Ranks memories accepted by `filter` by a blend of vector similarity to `query`
+    /// and keyword relevance to `text`, weighed by `ranking`, best match first.
+    ///
+    /// `model` is the embedding model `query` came from, see [`similarity`].
+    pub fn search(
+        &self,
+        query: &Embedding,
+        model: &str,
+        text: &str,
+        limit: usize,
+        ranking: &RankingConfig,
+        filter: impl Fn(&MyDoc) -> bool,
+    ) -> Vec<ScoredMemory> {
+        let now = Utc::now();
+        let candidates = self
+            .records
+            .values()
+            .filter(|record| filter(&record.doc))
+            .collect::<Vec<_>>();
+        let keyword_scores = self.keywords.scores(text);
+        // Normalised over the candidates only, so a strong match the filter
+        // left out doesn't flatten the keyword scores of everything else
+        let best_keyword = candidates
+            .iter()
+            .filter_map(|record| keyword_scores.get(&record.doc.id))
+            .copied()
+            .fold(0.0, f64::max);
+
+        let mut scored = candidates
+            .into_iter()
+            .map(|record| {
+                let vector_score = similarity(query, model, record).max(0.0);
+                let keyword_score = match keyword_scores.get(&record.doc.id) {
+                    Some(score) if best_keyword > 0.0 => score / best_keyword,
+                    _ => 0.0,
+                };
+
+                let relevance = VECTOR_WEIGHT * vector_score + (1.0 - VECTOR_WEIGHT) * keyword_score;
+
+                ScoredMemory {
+                    score: ranking.score(relevance, &record.doc, now),
+                    relevance,
+                    vector_score,
+                    keyword_score,
+                    recency_score: ranking.recency(&record.doc, now),
+                    doc: record.doc.clone(),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        scored.sort_by(|a, b| b.score.total_cmp(&a.score));
+        scored.truncate(limit);
+        scored
+    }
+
+    /// Returns the `n` memories accepted by `filter` that are most similar to
+    /// `query`, embedded by `model`, best match first.
+    pub fn top_n(
+        &self,
+        query: &Embedding,
+        model: &str,
+        n: usize,
+        filter: impl Fn(&MyDoc) -> bool,
+    ) -> Vec<(f64, MyDoc)> {
+        let mut scored = self
+            .records
+            .values()
+            .filter(|record| filter(&record.doc))
+            .map(|record| (similarity(query, model, record), record))
+            .collect::<Vec<_>>();
+
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored
+            .into_iter()
+            .take(n)
+            .map(|(score, record)| (score, record.doc.clone()))
+            .collect()
+    }
+
+    /// Groups memories in the same scope whose embeddings are closer than `threshold`.
+    ///
+    /// Each cluster is ordered oldest first and only clusters of two or more are returned.
+    /// Atomic truths are only grouped with truths about the same subject and attribute.
+    pub fn duplicate_clusters(&self, threshold: f64) -> Vec<Vec<MyDoc>> {
+        let mut records = self.records.values().collect::<Vec<_>>();
+        records.sort_by_key(|record| record.doc.updated_at);
+
+        let mut clustered = vec![false; records.len()];
+        let mut clusters = Vec::new();
+
+        for (i, record) in records.iter().enumerate() {
+            if clustered[i] {
+                continue;
+            }
+
+            let mut cluster = vec![record.doc.clone()];
+            for (j, other) in records.iter().enumerate().skip(i + 1) {
+                if clustered[j] || !mergeable(record, other, threshold) {
+                    continue;
+                }
+                clustered[j] = true;
+                cluster.push(other.doc.clone());
+            }
+
+            if cluster.len() > 1 {
+                clusters.push(cluster);
+            }
+        }
+
+        clusters
+    }
+}
+
+fn mergeable(a: &MemoryRecord, b: &MemoryRecord, threshold: f64) -> bool {
+    if a.doc.scope != b.doc.scope {
+        return false;
+    }
+    if let (Some(a), Some(b)) = (&a.doc.fact, &b.doc.fact)
+        && !a.same_key(b)
+    {
+        return false;
+    }
+
+    a.embeddings
+        .iter()
+        .any(|embedding| similarity(embedding, &a.embedding_model, b) > threshold)
+}
+
+/// A memory matches as well as its closest embedding.
+///
+/// Embeddings from different models aren't comparable even when they have the
+/// same number of dimensions, so a memory embedded by anything but `model`
+/// scores 0.
+fn similarity(query: &Embedding, model: &str, record: &MemoryRecord) -> f64 {
+    if record.embedding_model != model {
+        return 0.0;
+    }
+    record
+        .embeddings
+        .iter()
+        .map(|embedding| cosine_similarity(&query.vec, &embedding.vec))
+        .fold(f64::MIN, f64::max)
+}
+
+fn cosine_similarity(a: &[f64], b: &[f64]) -> f64 {
+    // Embeddings from different models aren't comparable
+    if a.len() != b.len() {
+        return 0.0;
+    }
+
+    let dot = a.iter().zip(b).map(|(x, y)| x * y).sum::<f64>();
+    let norm_a = a.iter().map(|x| x * x).sum::<f64>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f64>().sqrt();
+
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(id: &str, vec: Vec<f64>) -> MemoryRecord {
+        MemoryRecord {
+            doc: MyDoc {
+                id: id.to_string(),
+                summary: format!("memory {}", id),
+                ..Default::default()
+            },
+            embeddings: vec![Embedding {
+                document: id.to_string(),
+                vec,
+            }],
+            embedding_model: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_top_n_orders_by_similarity() {
+        let index = MemoryIndex::new(vec![
+            record("far", vec![0.0, 1.0]),
+            record("near", vec![1.0, 0.1]),
+            record("exact", vec![1.0, 0.0]),
+        ]);
+        let query = Embedding {
+            document: String::new(),
+            vec: vec![1.0, 0.0],
+        };
+
+        let ids = index
+            .top_n(&query, "", 2, |_| true)
+            .into_iter()
+            .map(|(_, doc)| doc.id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["exact", "near"]);
+    }
+
+    #[test]
+    fn test_search_boosts_keyword_matches() {
+        let index = MemoryIndex::new(vec![
+            record("near", vec![1.0, 0.1]),
+            record("exact", vec![0.9, 0.3]),
+        ]);
+        let query = Embedding {
+            document: String::new(),
+            vec: vec![1.0, 0.0],
+        };
+
+        let ranking = RankingConfig {
+            recency_weight: 0.0,
+            importance_weight: 0.0,
+            ..Default::default()
+        };
+
+        let results = index.search(&query, "", "exact", 2, &ranking, |_| true);
+        assert_eq!(results[0].doc.id, "exact");
+        assert_eq!(results[0].keyword_score, 1.0);
+        assert_eq!(results[1].keyword_score, 0.0);
+    }
+
+    #[test]
+    fn test_relevance_ignores_importance() {
+        let mut important = record("important", vec![0.0, 1.0]);
+        important.doc.importance = 1.0;
+        let index = MemoryIndex::new(vec![important, record("relevant", vec![1.0, 0.0])]);
+        let query = Embedding {
+            document: String::new(),
+            vec: vec![1.0, 0.0],
+        };
+
+        let ranking = RankingConfig {
+            recency_weight: 0.0,
+            importance_weight: 0.9,
+            ..Default::default()
+        };
+
+        let results = index.search(&query, "", "", 2, &ranking, |_| true);
+        assert_eq!(results[0].doc.id, "important");
+        assert_eq!(results[0].relevance, 0.0);
+        assert_eq!(results[1].relevance, VECTOR_WEIGHT);
+    }
+
+    #[test]
+    fn test_other_models_score_zero() {
+        let mut old = record("old", vec![1.0, 0.0]);
+        old.embedding_model = String::from("fastembed/all-minilm-l6-v2");
+        let index = MemoryIndex::new(vec![old, record("new", vec![0.5, 0.5])]);
+        let query = Embedding {
+            document: String::new(),
+            vec: vec![1.0, 0.0],
+        };
+
+        let results = index.top_n(&query, "", 2, |_| true);
+        assert_eq!(results[0].1.id, "new");
+        assert_eq!(results[1].0, 0.0);
+    }
+
+    #[test]
+    fn test_keyword_scores_ignore_filtered_out_memories() {
+        let mut other = record("other", vec![0.0, 1.0]);
+        other.doc.summary = String::from("Kestrel Kestrel Kestrel");
+        let mut mine = record("mine", vec![0.0, 1.0]);
+        mine.doc.summary = String::from("Kestrel project notes");
+        let index = MemoryIndex::new(vec![other, mine]);
+        let query = Embedding {
+            document: String::new(),
+            vec: vec![1.0, 0.0],
+        };
+
+        let results = index.search(&query, "", "Kestrel", 1, &RankingConfig::default(), |doc| doc.id == "mine");
+        assert_eq!(results[0].keyword_score, 1.0);
+    }
+
+    #[test]
+    fn test_insert_replaces_existing_id() {
+        let mut index = MemoryIndex::default();
+        index.insert(record("a", vec![1.0, 0.0]));
+        index.insert(record("a", vec![0.0, 1.0]));
+
+        assert_eq!(index.len(), 1);
+        assert_eq!(index.get("a").unwrap().embeddings[0].vec, vec![0.0, 1.0]);
+    }
+}
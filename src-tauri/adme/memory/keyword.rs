@@ -0,0 +1,126 @@
+//! BM25 keyword index over stored memories.
+//!
+//! Embeddings are poor at exact matches on names, IDs and project codes, so
+//! the keyword scores are blended with vector similarity during retrieval.
+
+use std::collections::HashMap;
+
+/// Term frequency saturation
+const K1: f64 = 1.2;
+/// Document length normalisation
+const B: f64 = 0.75;
+
+#[derive(Default)]
+pub struct KeywordIndex {
+    /// Term counts for each memory ID
+    documents: HashMap<String, HashMap<String, usize>>,
+    /// Number of memories each term appears in
+    document_frequency: HashMap<String, usize>,
+    total_terms: usize,
+}
+
+impl KeywordIndex {
+    /// Indexes `text` under `id`, replacing anything previously indexed for it.
+    pub fn insert(&mut self, id: &str, text: &str) {
+        self.remove(id);
+
+        let mut counts = HashMap::new();
+        for term in tokenize(text) {
+            *counts.entry(term).or_insert(0) += 1;
+        }
+        for term in counts.keys() {
+            *self.document_frequency.entry(term.clone()).or_insert(0) += 1;
+        }
+        self.total_terms += counts.values().sum::<usize>();
+        self.documents.insert(id.to_string(), counts);
+    }
+
+    pub fn remove(&mut self, id: &str) {
+        let Some(counts) = self.documents.remove(id) else {
+            return;
+        };
+
+        for term in counts.keys() {
+            if let Some(frequency) = self.document_frequency.get_mut(term) {
+                *frequency -= 1;
+                if *frequency == 0 {
+                    self.document_frequency.remove(term);
+                }
+            }
+        }
+        self.total_terms -= counts.values().sum::<usize>();
+    }
+
+    /// BM25 score of every memory that shares at least one term with `query`.
+    pub fn scores(&self, query: &str) -> HashMap<String, f64> {
+        let mut scores = HashMap::new();
+        if self.documents.is_empty() {
+            return scores;
+        }
+
+        let document_count = self.documents.len() as f64;
+        let average_length = self.total_terms as f64 / document_count;
+
+        let mut terms = tokenize(query);
+        terms.sort();
+        terms.dedup();
+
+        for term in terms {
+            let Some(&frequency) = self.document_frequency.get(&term) else {
+                continue;
+            };
+            let idf = ((document_count - frequency as f64 + 0.5) / (frequency as f64 + 0.5) + 1.0).ln();
+
+            for (id, counts) in &self.documents {
+                let Some(&count) = counts.get(&term) else {
+                    continue;
+                };
+                let count = count as f64;
+                let length = counts.values().sum::<usize>() as f64;
+                let score = idf * count * (K1 + 1.0)
+                    / (count + K1 * (1.0 - B + B * length / average_length));
+                *scores.entry(id.clone()).or_insert(0.0) += score;
+            }
+        }
+
+        scores
+    }
+}
+
+/// Lowercases and splits on anything that isn't alphanumeric, keeping `-` and `_` so codes like `PRJ-42` stay whole.
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_'))
+        .map(|term| term.trim_matches(|c| c == '-' || c == '_').to_lowercase())
+        .filter(|term| !term.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exact_code_outranks_common_words() {
+        let mut index = KeywordIndex::default();
+        index.insert("a", "Project PRJ-42 uses Vulkan for the renderer");
+        index.insert("b", "Project renderer uses OpenGL");
+        index.insert("c", "User prefers dark themes");
+
+        let scores = index.scores("status of prj-42");
+        assert!(scores["a"] > 0.0);
+        assert!(!scores.contains_key("b"));
+        assert!(!scores.contains_key("c"));
+    }
+
+    #[test]
+    fn test_remove_forgets_terms() {
+        let mut index = KeywordIndex::default();
+        index.insert("a", "Vulkan renderer");
+        index.insert("a", "OpenGL renderer");
+        assert!(index.scores("vulkan").is_empty());
+
+        index.remove("a");
+        assert!(index.scores("renderer").is_empty());
+        assert_eq!(index.total_terms, 0);
+    }
+}
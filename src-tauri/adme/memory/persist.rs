@@ -0,0 +1,91 @@
+//! On-disk persistence for the Adme memory store.
+//!
+//! Memories are kept as JSON lines, one document per line together with the
+//! embeddings computed for it, so the store can be rebuilt at startup without
+//! re-running the embedding model.
+
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    path::Path,
+};
+
+use rig::embeddings::Embedding;
+use serde::{Deserialize, Serialize};
+
+use crate::adme::memory::{EmbeddingConfig, MyDoc};
+
+/// A single persisted memory and its embeddings.
+#[derive(Serialize, Deserialize)]
+pub struct MemoryRecord {
+    pub doc: MyDoc,
+    pub embeddings: Vec<Embedding>,
+    /// Model that produced `embeddings`, see [`EmbeddingConfig::id`]
+    #[serde(default = "legacy_embedding_model")]
+    pub embedding_model: String,
+}
+
+/// Stores written before the model was recorded were all embedded with the original default.
+fn legacy_embedding_model() -> String {
+    EmbeddingConfig::default().id()
+}
+
+/// Loads every record from `path`, returning an empty list if the file does not exist yet.
+pub fn load(path: &Path) -> anyhow::Result<Vec<MemoryRecord>> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| Ok(serde_json::from_str(line)?))
+        .collect()
+}
+
+/// Locks the store at `path` for this process until the returned file is dropped.
+///
+/// Every write replaces the whole store, so two processes writing to it would
+/// quietly undo each other's changes.
+pub fn lock(path: &Path) -> anyhow::Result<File> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(path.with_extension("jsonl.lock"))?;
+    match file.try_lock() {
+        Ok(()) => Ok(file),
+        Err(TryLockError::WouldBlock) => anyhow::bail!(
+            "{} is in use by another seedling process, close it and try again",
+            path.display()
+        ),
+        Err(TryLockError::Error(e)) => Err(e.into()),
+    }
+}
+
+/// Writes all records to `path`, replacing the previous contents atomically.
+pub async fn save<'a>(
+    path: &Path,
+    records: impl IntoIterator<Item = &'a MemoryRecord>,
+) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+
+    let mut contents = String::new();
+    for record in records {
+        contents.push_str(&serde_json::to_string(record)?);
+        contents.push('\n');
+    }
+
+    // Write to a sibling file first so a crash mid-write never truncates the store
+    let tmp_path = path.with_extension("jsonl.tmp");
+    tokio::fs::write(&tmp_path, contents).await?;
+    tokio::fs::rename(&tmp_path, path).await?;
+
+    Ok(())
+}
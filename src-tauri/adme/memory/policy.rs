@@ -0,0 +1,163 @@
+//! Tunable behaviour of the memory store: how new memories are reconciled
+//! with similar ones, how results are ranked and how the store is kept tidy.
+
+use std::{str::FromStr, time::Duration};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::adme::memory::{EmbeddingConfig, EmbeddingProvider, MyDoc};
+
+/// What to do when a new memory closely matches an existing one.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MergePolicy {
+    /// Always store the new memory alongside the existing one
+    AlwaysAppend,
+    /// Fold the new memory into the existing one, keeping the old version in its history
+    #[default]
+    Merge,
+    /// Leave both untouched and have the agent confirm with the user first
+    AskUser,
+}
+
+impl MergePolicy {
+    /// The policy to use when nobody is around to confirm a merge, where
+    /// asking would lose the memory.
+    pub fn unattended(self) -> Self {
+        match self {
+            Self::AskUser => Self::Merge,
+            policy => policy,
+        }
+    }
+}
+
+impl FromStr for MergePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "always_append" | "append" => Ok(Self::AlwaysAppend),
+            "merge" => Ok(Self::Merge),
+            "ask_user" | "ask" => Ok(Self::AskUser),
+            other => anyhow::bail!("Unknown memory merge policy: {}", other),
+        }
+    }
+}
+
+/// How relevance is blended with recency and importance when ranking memories.
+#[derive(Clone, Debug)]
+pub struct RankingConfig {
+    pub recency_weight: f64,
+    pub importance_weight: f64,
+    /// Days without use after which a memory's recency score halves
+    pub recency_half_life_days: f64,
+}
+
+impl Default for RankingConfig {
+    fn default() -> Self {
+        Self {
+            recency_weight: 0.1,
+            importance_weight: 0.1,
+            recency_half_life_days: 30.0,
+        }
+    }
+}
+
+impl RankingConfig {
+    /// Combines a query relevance between 0 and 1 with the memory's recency and importance.
+    pub fn score(&self, relevance: f64, doc: &MyDoc, now: DateTime<Utc>) -> f64 {
+        let relevance_weight = 1.0 - self.recency_weight - self.importance_weight;
+        relevance_weight * relevance
+            + self.recency_weight * self.recency(doc, now)
+            + self.importance_weight * doc.importance
+    }
+
+    /// Exponential decay from 1 for a memory used just now towards 0 for one long forgotten.
+    pub fn recency(&self, doc: &MyDoc, now: DateTime<Utc>) -> f64 {
+        let age_days = (now - doc.last_used()).num_seconds().max(0) as f64 / 86_400.0;
+        0.5_f64.powf(age_days / self.recency_half_life_days)
+    }
+}
+
+/// When the periodic consolidation job runs and what it considers stale.
+#[derive(Clone, Debug)]
+pub struct ConsolidationConfig {
+    pub interval: Duration,
+    /// Memories unused for this long are candidates for summarisation
+    pub stale_after_days: i64,
+    /// Only stale memories less important than this are summarised
+    pub stale_importance: f64,
+}
+
+impl Default for ConsolidationConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(6 * 60 * 60),
+            stale_after_days: 90,
+            stale_importance: 0.3,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MemoryConfig {
+    /// Cosine similarity above which two memories are considered the same
+    pub similarity_threshold: f64,
+    pub merge_policy: MergePolicy,
+    /// Importance given to new memories unless the agent says otherwise
+    pub default_importance: f64,
+    pub ranking: RankingConfig,
+    pub consolidation: ConsolidationConfig,
+    pub embedding: EmbeddingConfig,
+}
+
+impl Default for MemoryConfig {
+    fn default() -> Self {
+        Self {
+            similarity_threshold: 0.85,
+            merge_policy: MergePolicy::default(),
+            default_importance: 0.5,
+            ranking: RankingConfig::default(),
+            consolidation: ConsolidationConfig::default(),
+            embedding: EmbeddingConfig::default(),
+        }
+    }
+}
+
+impl MemoryConfig {
+    /// Reads `ADME_MEMORY_SIM_THRESHOLD`, `ADME_MEMORY_MERGE_POLICY`,
+    /// `ADME_MEMORY_CONSOLIDATE_HOURS`, `ADME_EMBEDDING_PROVIDER` and
+    /// `ADME_EMBEDDING_MODEL`, falling back to the defaults.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let mut config = Self::default();
+
+        if let Ok(threshold) = std::env::var("ADME_MEMORY_SIM_THRESHOLD") {
+            config.similarity_threshold = threshold.parse()?;
+        }
+        if let Ok(policy) = std::env::var("ADME_MEMORY_MERGE_POLICY") {
+            config.merge_policy = policy.parse()?;
+        }
+        if let Ok(hours) = std::env::var("ADME_MEMORY_CONSOLIDATE_HOURS") {
+            let interval = Duration::try_from_secs_f64(hours.parse::<f64>()? * 3600.0)
+                .ok()
+                // `tokio::time::interval` panics on a zero period
+                .filter(|interval| !interval.is_zero());
+            let Some(interval) = interval else {
+                anyhow::bail!("ADME_MEMORY_CONSOLIDATE_HOURS must be a positive number of hours, got {}", hours);
+            };
+            config.consolidation.interval = interval;
+        }
+        if let Ok(provider) = std::env::var("ADME_EMBEDDING_PROVIDER") {
+            config.embedding.provider = provider.parse()?;
+            if config.embedding.provider == EmbeddingProvider::Ollama {
+                config.embedding.model = String::from("nomic-embed-text");
+            }
+        }
+        if let Ok(model) = std::env::var("ADME_EMBEDDING_MODEL") {
+            config.embedding.model = model;
+        }
+
+        Ok(config)
+    }
+}
@@ -0,0 +1,63 @@
+//! Search parameters for retrieving memories.
+
+use chrono::{DateTime, Utc};
+
+use crate::adme::memory::{MyDoc, Scope};
+
+/// Number of memories returned when the caller doesn't ask for a limit.
+pub const DEFAULT_LIMIT: usize = 2;
+
+pub struct MemoryQuery {
+    /// Free text matched against memories by both embedding and keywords
+    pub text: String,
+    pub limit: usize,
+    /// Memories less relevant than this are dropped, before recency and importance are weighed in
+    pub min_score: f64,
+    /// Only atomic truths about this subject
+    pub subject: Option<String>,
+    /// Only memories carrying every one of these tags
+    pub tags: Vec<String>,
+    /// Only memories last updated at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Only memories last updated at or before this time
+    pub until: Option<DateTime<Utc>>,
+    /// Only memories in one of these scopes, any scope when empty
+    pub scopes: Vec<Scope>,
+}
+
+impl MemoryQuery {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            limit: DEFAULT_LIMIT,
+            min_score: 0.0,
+            subject: None,
+            tags: Vec::new(),
+            since: None,
+            until: None,
+            scopes: Vec::new(),
+        }
+    }
+
+    /// Whether `doc` passes the metadata filters of this query.
+    pub fn matches(&self, doc: &MyDoc) -> bool {
+        if !self.scopes.is_empty() && !self.scopes.contains(&doc.scope) {
+            return false;
+        }
+
+        if let Some(subject) = &self.subject {
+            if !doc.fact.as_ref().is_some_and(|fact| fact.is_about(subject)) {
+                return false;
+            }
+        }
+
+        let has_tags = self
+            .tags
+            .iter()
+            .all(|tag| doc.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)));
+
+        has_tags
+            && self.since.is_none_or(|since| doc.updated_at >= since)
+            && self.until.is_none_or(|until| doc.updated_at <= until)
+    }
+}
@@ -0,0 +1,154 @@
+//! Memory namespaces.
+//!
+//! Every memory belongs to one scope. A conversation can see the scopes of
+//! its user, chat and project plus the shared global scope, so personal facts
+//! learned in one chat don't surface in someone else's answers.
+
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, Eq, PartialEq, Hash)]
+#[serde(into = "String", try_from = "String")]
+pub enum Scope {
+    /// Shared by every conversation
+    #[default]
+    Global,
+    User(String),
+    Chat(String),
+    Project(String),
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scope::Global => write!(f, "global"),
+            Scope::User(id) => write!(f, "user:{}", id),
+            Scope::Chat(id) => write!(f, "chat:{}", id),
+            Scope::Project(name) => write!(f, "project:{}", name),
+        }
+    }
+}
+
+impl FromStr for Scope {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "global" {
+            return Ok(Scope::Global);
+        }
+
+        match s.split_once(':') {
+            Some(("user", id)) if !id.is_empty() => Ok(Scope::User(id.to_string())),
+            Some(("chat", id)) if !id.is_empty() => Ok(Scope::Chat(id.to_string())),
+            Some(("project", name)) if !name.is_empty() => Ok(Scope::Project(name.to_string())),
+            _ => anyhow::bail!("Invalid memory scope: {}", s),
+        }
+    }
+}
+
+impl From<Scope> for String {
+    fn from(scope: Scope) -> Self {
+        scope.to_string()
+    }
+}
+
+impl TryFrom<String> for Scope {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// The kind of scope an agent asks to store a memory in.
+#[derive(Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScopeKind {
+    Global,
+    User,
+    Chat,
+    Project,
+}
+
+/// The scopes visible to a single conversation.
+#[derive(Clone, Debug, Default)]
+pub struct Namespace {
+    pub user: Option<String>,
+    pub chat: Option<String>,
+    pub project: Option<String>,
+}
+
+impl Namespace {
+    /// Namespace for a conversation with `user` in `chat`, in the project named by `ADME_PROJECT` if set.
+    pub fn for_conversation(user: impl ToString, chat: impl ToString) -> Self {
+        Self {
+            user: Some(user.to_string()),
+            chat: Some(chat.to_string()),
+            project: std::env::var("ADME_PROJECT").ok().filter(|p| !p.is_empty()),
+        }
+    }
+
+    /// Every scope this conversation may read, narrowest first.
+    pub fn readable(&self) -> Vec<Scope> {
+        let mut scopes = Vec::new();
+        scopes.extend(self.chat.clone().map(Scope::Chat));
+        scopes.extend(self.user.clone().map(Scope::User));
+        scopes.extend(self.project.clone().map(Scope::Project));
+        scopes.push(Scope::Global);
+        scopes
+    }
+
+    pub fn can_read(&self, scope: &Scope) -> bool {
+        self.readable().contains(scope)
+    }
+
+    /// Resolves `kind` to a concrete scope, or `None` if this conversation has no such scope.
+    pub fn resolve(&self, kind: ScopeKind) -> Option<Scope> {
+        match kind {
+            ScopeKind::Global => Some(Scope::Global),
+            ScopeKind::User => self.user.clone().map(Scope::User),
+            ScopeKind::Chat => self.chat.clone().map(Scope::Chat),
+            ScopeKind::Project => self.project.clone().map(Scope::Project),
+        }
+    }
+
+    /// Where memories go when the agent doesn't pick a scope: facts are usually about the person talking.
+    pub fn default_scope(&self) -> Scope {
+        self.resolve(ScopeKind::User)
+            .or_else(|| self.resolve(ScopeKind::Chat))
+            .unwrap_or(Scope::Global)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scope_round_trips_through_string() {
+        for scope in [
+            Scope::Global,
+            Scope::User("42".to_string()),
+            Scope::Chat("-100".to_string()),
+            Scope::Project("seedling".to_string()),
+        ] {
+            assert_eq!(scope.to_string().parse::<Scope>().unwrap(), scope);
+        }
+        assert!("team:core".parse::<Scope>().is_err());
+    }
+
+    #[test]
+    fn test_namespace_hides_other_users() {
+        let namespace = Namespace {
+            user: Some("1".to_string()),
+            chat: Some("1".to_string()),
+            project: None,
+        };
+
+        assert!(namespace.can_read(&Scope::Global));
+        assert!(namespace.can_read(&Scope::User("1".to_string())));
+        assert!(!namespace.can_read(&Scope::User("2".to_string())));
+        assert_eq!(namespace.default_scope(), Scope::User("1".to_string()));
+    }
+}
@@ -0,0 +1,48 @@
+//! JSONL import and export of the memory store.
+//!
+//! Unlike the store's own file, an export is meant to be read and edited by
+//! hand, so embeddings are optional and tagged with the model that produced
+//! them. Imports re-embed anything produced by a different model.
+
+use std::path::Path;
+
+use rig::embeddings::Embedding;
+use serde::{Deserialize, Serialize};
+
+use crate::adme::memory::MyDoc;
+
+#[derive(Serialize, Deserialize)]
+pub struct ExportRecord {
+    #[serde(flatten)]
+    pub doc: MyDoc,
+    /// Model that produced `embeddings`, absent when they weren't exported
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding_model: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embeddings: Option<Vec<Embedding>>,
+}
+
+pub async fn write(path: &Path, records: &[ExportRecord]) -> anyhow::Result<()> {
+    let mut contents = String::new();
+    for record in records {
+        contents.push_str(&serde_json::to_string(record)?);
+        contents.push('\n');
+    }
+
+    tokio::fs::write(path, contents).await?;
+    Ok(())
+}
+
+pub async fn read(path: &Path) -> anyhow::Result<Vec<ExportRecord>> {
+    let contents = tokio::fs::read_to_string(path).await?;
+
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(number, line)| {
+            serde_json::from_str(line)
+                .map_err(|e| anyhow::anyhow!("Invalid memory on line {}: {}", number + 1, e))
+        })
+        .collect()
+}
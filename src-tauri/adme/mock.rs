@@ -0,0 +1,297 @@
+//! A scripted stand-in for the Ollama HTTP API, so agents can be tested
+//! without a GPU.
+//!
+//! Tests queue the chat replies they expect, in order, optionally with text
+//! the request must contain. Every `/api/chat` request takes the next reply,
+//! and a request nobody scripted, or one missing the expected text, fails with
+//! a 500 so the agent under test errors out. `/api/embed` answers with a
+//! bag-of-words embedding, so texts sharing most of their words come out
+//! similar.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use serde_json::{Value, json};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::adme::{
+    agents::Agents,
+    memory::{EmbeddingConfig, EmbeddingProvider, MemoryConfig},
+    provider::Providers,
+};
+
+/// Dimensions of the mock embeddings.
+const EMBEDDING_DIMS: usize = 64;
+
+pub enum MockReply {
+    Text(String),
+    ToolCall { name: String, arguments: Value },
+}
+
+impl MockReply {
+    pub fn text(text: &str) -> Self {
+        Self::Text(text.to_string())
+    }
+
+    pub fn tool_call(name: &str, arguments: Value) -> Self {
+        Self::ToolCall {
+            name: name.to_string(),
+            arguments,
+        }
+    }
+}
+
+struct Scripted {
+    /// Text the request body must contain
+    expect: Option<String>,
+    reply: MockReply,
+}
+
+#[derive(Default)]
+struct Script {
+    replies: VecDeque<Scripted>,
+    chat_requests: Vec<Value>,
+    failures: Vec<String>,
+}
+
+pub struct MockOllama {
+    url: String,
+    script: Arc<Mutex<Script>>,
+}
+
+impl MockOllama {
+    /// Starts serving on a free local port until the test's runtime shuts down.
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let script = Arc::new(Mutex::new(Script::default()));
+
+        let server_script = script.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, server_script.clone()));
+            }
+        });
+
+        Self { url, script }
+    }
+
+    /// Queues the reply to the next chat request.
+    pub fn reply(&self, reply: MockReply) -> &Self {
+        self.push(None, reply)
+    }
+
+    /// Queues the reply to the next chat request, which must contain `text`.
+    pub fn expect(&self, text: &str, reply: MockReply) -> &Self {
+        self.push(Some(text.to_string()), reply)
+    }
+
+    fn push(&self, expect: Option<String>, reply: MockReply) -> &Self {
+        self.script.lock().unwrap().replies.push_back(Scripted { expect, reply });
+        self
+    }
+
+    /// Bodies of every chat request received so far.
+    pub fn chat_requests(&self) -> Vec<Value> {
+        self.script.lock().unwrap().chat_requests.clone()
+    }
+
+    /// Panics unless every scripted reply was used and nothing unexpected came in.
+    pub fn assert_done(&self) {
+        let script = self.script.lock().unwrap();
+        assert!(script.failures.is_empty(), "mock Ollama failures: {:?}", script.failures);
+        assert!(
+            script.replies.is_empty(),
+            "{} scripted replies were never requested",
+            script.replies.len()
+        );
+    }
+
+    pub fn providers(&self) -> Arc<Providers> {
+        Arc::new(Providers::new(&self.url, &self.url, "none").unwrap())
+    }
+
+    /// The built-in agents, talking to this mock.
+    pub fn agents(&self) -> Arc<Agents> {
+        Arc::new(Agents::new(self.providers()))
+    }
+
+    /// Memory settings that embed through this mock rather than fastembed.
+    pub fn memory_config() -> MemoryConfig {
+        MemoryConfig {
+            embedding: EmbeddingConfig {
+                provider: EmbeddingProvider::Ollama,
+                model: String::from("mock-embed"),
+            },
+            ..MemoryConfig::default()
+        }
+    }
+}
+
+async fn serve(mut stream: TcpStream, script: Arc<Mutex<Script>>) {
+    let Some((path, body)) = read_request(&mut stream).await else {
+        return;
+    };
+
+    let (status, content_type, response) = match path.as_str() {
+        "/api/chat" => chat(&body, &script),
+        "/api/embed" => (200, "application/json", embed(&body).to_string()),
+        _ => (404, "application/json", json!({"error": "not found"}).to_string()),
+    };
+
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        if status == 200 { "OK" } else { "Error" },
+        content_type,
+        response.len()
+    );
+    let _ = stream.write_all(head.as_bytes()).await;
+    let _ = stream.write_all(response.as_bytes()).await;
+}
+
+/// Reads one request, returning its path and JSON body.
+async fn read_request(stream: &mut TcpStream) -> Option<(String, Value)> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        let n = stream.read(&mut chunk).await.ok()?;
+        if n == 0 {
+            return None;
+        }
+        buffer.extend_from_slice(&chunk[..n]);
+        if let Some(end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..header_end]).to_string();
+    let path = head.split_whitespace().nth(1)?.to_string();
+    let length: usize = head
+        .lines()
+        .find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.eq_ignore_ascii_case("content-length").then(|| value.trim().parse().ok())?
+        })
+        .unwrap_or(0);
+
+    while buffer.len() < header_end + length {
+        let n = stream.read(&mut chunk).await.ok()?;
+        if n == 0 {
+            break;
+        }
+        buffer.extend_from_slice(&chunk[..n]);
+    }
+
+    let body = serde_json::from_slice(&buffer[header_end..]).unwrap_or(Value::Null);
+    Some((path, body))
+}
+
+fn chat(body: &Value, script: &Mutex<Script>) -> (u16, &'static str, String) {
+    let mut script = script.lock().unwrap();
+    script.chat_requests.push(body.clone());
+
+    let failure = match script.replies.pop_front() {
+        None => String::from("chat request with nothing scripted"),
+        Some(Scripted { expect: Some(text), .. }) if !body.to_string().contains(&json_escaped(&text)) => {
+            format!("chat request without '{}'", text)
+        }
+        Some(scripted) => {
+            let model = body["model"].as_str().unwrap_or("mock");
+            let stream = body["stream"].as_bool().unwrap_or(false);
+            let content_type = if stream { "application/x-ndjson" } else { "application/json" };
+            return (200, content_type, render(model, scripted.reply, stream));
+        }
+    };
+
+    script.failures.push(failure.clone());
+    (500, "application/json", json!({ "error": failure }).to_string())
+}
+
+/// `text` as it appears inside a serialized JSON string.
+fn json_escaped(text: &str) -> String {
+    let quoted = Value::String(text.to_string()).to_string();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+fn render(model: &str, reply: MockReply, stream: bool) -> String {
+    let message = match &reply {
+        MockReply::Text(text) => json!({"role": "assistant", "content": text}),
+        MockReply::ToolCall { name, arguments } => json!({
+            "role": "assistant",
+            "content": "",
+            "tool_calls": [{"type": "function", "function": {"name": name, "arguments": arguments}}],
+        }),
+    };
+    let done = |message: Value| {
+        json!({
+            "model": model,
+            "created_at": "2024-01-01T00:00:00Z",
+            "message": message,
+            "done": true,
+            "done_reason": "stop",
+            "total_duration": 1,
+            "load_duration": 1,
+            "prompt_eval_count": 1,
+            "prompt_eval_duration": 1,
+            "eval_count": 1,
+            "eval_duration": 1,
+        })
+    };
+
+    let MockReply::Text(text) = &reply else {
+        return done(message).to_string() + "\n";
+    };
+    if !stream {
+        return done(message).to_string();
+    }
+
+    // One line per word, as a real stream would trickle them out
+    let mut lines: Vec<String> = text
+        .split_inclusive(' ')
+        .map(|word| {
+            json!({
+                "model": model,
+                "created_at": "2024-01-01T00:00:00Z",
+                "message": {"role": "assistant", "content": word},
+                "done": false,
+            })
+            .to_string()
+        })
+        .collect();
+    lines.push(done(json!({"role": "assistant", "content": ""})).to_string());
+    lines.join("\n") + "\n"
+}
+
+fn embed(body: &Value) -> Value {
+    let inputs: Vec<String> = match &body["input"] {
+        Value::String(text) => vec![text.clone()],
+        Value::Array(texts) => texts.iter().filter_map(|text| text.as_str().map(str::to_string)).collect(),
+        _ => vec![],
+    };
+    json!({
+        "model": body["model"],
+        "embeddings": inputs.iter().map(|text| bag_of_words(text)).collect::<Vec<_>>(),
+        "total_duration": 1,
+        "load_duration": 1,
+        "prompt_eval_count": 1,
+    })
+}
+
+fn bag_of_words(text: &str) -> Vec<f64> {
+    let mut vector = vec![0.0; EMBEDDING_DIMS];
+    for word in text.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()) {
+        // FNV-1a, so the same word always lands in the same dimension
+        let hash = word
+            .to_lowercase()
+            .bytes()
+            .fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3));
+        vector[(hash % EMBEDDING_DIMS as u64) as usize] += 1.0;
+    }
+    vector
+}
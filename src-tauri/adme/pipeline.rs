@@ -0,0 +1,745 @@
+//! The chain of agent stages Adme runs to answer a prompt.
+//!
+//! A pipeline is a DAG of stages. Each stage is one LLM call with its own
+//! [`AgentSettings`], memory tools and input template, and it can read the
+//! output of the stages it depends on. The one stage nothing depends on is
+//! the output stage, and its reply is what the user sees.
+//!
+//! The pipeline is read from `ADME_PIPELINE` (default `pipeline.json` in the
+//! data dir) and can be replaced at runtime from the React Flow graph in the
+//! Agents tab. Without a config file the planner feeds the translator, as
+//! defined in [`planner`](super::planner) and [`translator`](super::translator).
+//!
+//! Every prompt is kept within the stage's context window as described in
+//! [`budget`](super::budget).
+//!
+//! Stages marked `skip_for_chit_chat` don't run when the
+//! [`router`](super::router) finds the prompt is only small talk, and their
+//! output reads as empty.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use futures::future::join_all;
+use rig::tool::ToolDyn;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::{
+    adme::{
+        AdmeError,
+        agents::AgentSettings,
+        backend::Backend,
+        budget::{BudgetConfig, estimate_tokens, truncate},
+        data_dir,
+        memory::Memory,
+        memory::Namespace, planner, provider::Providers,
+        templates::{self, GLOBAL_VARS, TemplateError, Templates, render, variables},
+        tools::ToolKind,
+        trace::{StageTrace, TokenUsage, ToolCalls, TracedTool},
+        translator,
+    },
+    filters::{ThinkFilter, filter_think_tag},
+};
+
+/// How long a stage may run unless its settings say otherwise.
+const DEFAULT_TIMEOUT_SECS: u64 = 180;
+
+/// Template variable holding the user's prompt.
+const PROMPT_VAR: &str = "prompt";
+/// Template variable holding the rendered conversation history, empty for a new chat.
+const CONVERSATION_VAR: &str = "conversation";
+
+/// Everything about a stage except where it sits in the graph.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct StageSettings {
+    #[serde(flatten)]
+    pub agent: AgentSettings,
+    /// Memory tools the stage may call
+    #[serde(default)]
+    pub tools: Vec<ToolKind>,
+    /// Prompt sent to the stage, see [`render`] for the variables it may use
+    pub input: String,
+    /// Deadline for the stage's LLM call, not counting time queued for the backend
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+    /// Left out for greetings, thanks and other prompts the router finds trivial
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub skip_for_chit_chat: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct StageConfig {
+    pub id: String,
+    /// Stages whose output this one reads
+    #[serde(default)]
+    pub inputs: Vec<String>,
+    #[serde(flatten)]
+    pub settings: StageSettings,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PipelineConfig {
+    pub stages: Vec<StageConfig>,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            stages: vec![planner::stage(), translator::stage()],
+        }
+    }
+}
+
+/// A pipeline as the React Flow graph in the Agents tab describes it.
+///
+/// Node positions, types and labels are ignored, an edge means its target
+/// reads the output of its source.
+#[derive(Deserialize, Debug)]
+pub struct PipelineGraph {
+    pub nodes: Vec<GraphNode>,
+    #[serde(default)]
+    pub edges: Vec<GraphEdge>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GraphNode {
+    pub id: String,
+    pub data: StageSettings,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GraphEdge {
+    pub source: String,
+    pub target: String,
+}
+
+impl From<PipelineGraph> for PipelineConfig {
+    fn from(graph: PipelineGraph) -> Self {
+        let stages = graph
+            .nodes
+            .into_iter()
+            .map(|node| StageConfig {
+                inputs: graph
+                    .edges
+                    .iter()
+                    .filter(|edge| edge.target == node.id)
+                    .map(|edge| edge.source.clone())
+                    .collect(),
+                id: node.id,
+                settings: node.data,
+            })
+            .collect();
+        Self { stages }
+    }
+}
+
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum PipelineError {
+    #[error("the pipeline has no stages")]
+    Empty,
+    #[error("stage '{0}' is defined more than once")]
+    DuplicateStage(String),
+    #[error("stage '{stage}' reads from unknown stage '{input}'")]
+    UnknownInput { stage: String, input: String },
+    #[error("stages {0:?} depend on each other in a cycle")]
+    Cycle(Vec<String>),
+    #[error("exactly one stage must be the output, found {0:?}")]
+    Outputs(Vec<String>),
+    #[error("stage '{stage}' uses '{{{{{name}}}}}', which is not {PROMPT_VAR}, {CONVERSATION_VAR}, {GLOBAL_VARS:?} or one of its inputs")]
+    UnknownVariable { stage: String, name: String },
+    #[error("stage '{stage}' has an unterminated '{{{{' in its input")]
+    Unterminated { stage: String },
+    #[error("the output stage '{0}' always runs, it can't be skipped for chit-chat")]
+    SkippedOutput(String),
+    #[error(transparent)]
+    Preamble(#[from] TemplateError),
+}
+
+/// Everything a run of the pipeline needs besides the stages.
+pub struct RunContext<'a> {
+    pub prompt: &'a str,
+    pub conversation: &'a str,
+    pub memory: &'a Arc<Memory>,
+    pub namespace: &'a Namespace,
+    pub backend: &'a Backend,
+    pub providers: &'a Providers,
+    pub budget: &'a BudgetConfig,
+    pub templates: &'a Templates,
+    /// Set when the router found the prompt is only small talk
+    pub chit_chat: bool,
+    /// Every stage adds itself here once it is done, whether it worked or not
+    pub trace: &'a Mutex<Vec<StageTrace>>,
+}
+
+/// A validated [`PipelineConfig`], ready to run.
+pub struct Pipeline {
+    config: PipelineConfig,
+    /// Stages grouped so each group only depends on earlier ones, the output stage last
+    levels: Vec<Vec<usize>>,
+}
+
+impl Pipeline {
+    pub fn new(config: PipelineConfig) -> Result<Self, PipelineError> {
+        let stages = &config.stages;
+        if stages.is_empty() {
+            return Err(PipelineError::Empty);
+        }
+
+        let mut index = HashMap::new();
+        for (i, stage) in stages.iter().enumerate() {
+            if index.insert(stage.id.as_str(), i).is_some() {
+                return Err(PipelineError::DuplicateStage(stage.id.clone()));
+            }
+        }
+
+        for stage in stages {
+            for input in &stage.inputs {
+                if !index.contains_key(input.as_str()) {
+                    return Err(PipelineError::UnknownInput {
+                        stage: stage.id.clone(),
+                        input: input.clone(),
+                    });
+                }
+            }
+            stage.settings.agent.validate(&stage.id)?;
+            for name in variables(&stage.settings.input).ok_or_else(|| PipelineError::Unterminated {
+                stage: stage.id.clone(),
+            })? {
+                if name != PROMPT_VAR
+                    && name != CONVERSATION_VAR
+                    && !GLOBAL_VARS.contains(&name)
+                    && !stage.inputs.iter().any(|input| input == name)
+                {
+                    return Err(PipelineError::UnknownVariable {
+                        stage: stage.id.clone(),
+                        name: name.to_string(),
+                    });
+                }
+            }
+        }
+
+        let read: HashSet<&str> = stages
+            .iter()
+            .flat_map(|stage| stage.inputs.iter().map(String::as_str))
+            .collect();
+        let outputs: Vec<String> = stages
+            .iter()
+            .filter(|stage| !read.contains(stage.id.as_str()))
+            .map(|stage| stage.id.clone())
+            .collect();
+
+        // Kahn's algorithm, one level at a time so independent stages can run together
+        let mut level_of: Vec<Option<usize>> = vec![None; stages.len()];
+        let mut levels: Vec<Vec<usize>> = Vec::new();
+        loop {
+            let ready: Vec<usize> = (0..stages.len())
+                .filter(|&i| level_of[i].is_none())
+                .filter(|&i| stages[i].inputs.iter().all(|input| level_of[index[input.as_str()]].is_some()))
+                .collect();
+            if ready.is_empty() {
+                break;
+            }
+            for &i in &ready {
+                level_of[i] = Some(levels.len());
+            }
+            levels.push(ready);
+        }
+
+        let unresolved: Vec<String> = (0..stages.len())
+            .filter(|&i| level_of[i].is_none())
+            .map(|i| stages[i].id.clone())
+            .collect();
+        if !unresolved.is_empty() {
+            return Err(PipelineError::Cycle(unresolved));
+        }
+        // A single output is downstream of every other stage, so it ends up alone in the last level
+        if outputs.len() != 1 {
+            return Err(PipelineError::Outputs(outputs));
+        }
+        if stages[index[outputs[0].as_str()]].settings.skip_for_chit_chat {
+            return Err(PipelineError::SkippedOutput(outputs[0].clone()));
+        }
+
+        Ok(Self { config, levels })
+    }
+
+    /// Loads the pipeline at `path`, or the default one if there is no file.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::new(PipelineConfig::default())?);
+        }
+        let config: PipelineConfig = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        println!("Loaded {} stage pipeline from {}", config.stages.len(), path.display());
+        Ok(Self::new(config)?)
+    }
+
+    pub async fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(path, serde_json::to_string_pretty(&self.config)?).await?;
+        Ok(())
+    }
+
+    pub fn config(&self) -> &PipelineConfig {
+        &self.config
+    }
+
+    /// Runs every stage and streams the output stage's reply to `tokens`.
+    ///
+    /// Returns the whole reply with any `<think>` block removed.
+    pub async fn run(
+        &self,
+        context: &RunContext<'_>,
+        tokens: &UnboundedSender<Result<String, AdmeError>>,
+    ) -> Result<String, AdmeError> {
+        let mut outputs: HashMap<&str, String> = HashMap::new();
+        let (output, upstream) = self.levels.split_last().expect("a valid pipeline has stages");
+
+        for level in upstream {
+            let (skipped, level): (Vec<usize>, Vec<usize>) = level
+                .iter()
+                .partition(|&&i| context.chit_chat && self.config.stages[i].settings.skip_for_chit_chat);
+            for i in skipped {
+                context.trace.lock().unwrap().push(StageTrace::skipped(&self.config.stages[i].id));
+            }
+
+            let results = join_all(level.into_iter().map(|i| {
+                let stage = &self.config.stages[i];
+                let input = self.input(stage, context, &outputs);
+                async move {
+                    let _permit = context.backend.permit().await;
+                    let slot = stage.start(context, &input);
+                    let calls = ToolCalls::default();
+                    let started = Instant::now();
+                    let result = tokio::time::timeout(stage.timeout(), stage.complete(&input, context, &calls))
+                        .await
+                        .map_err(anyhow::Error::from)
+                        .and_then(|output| output);
+                    stage.trace(context, slot, input, &result, calls, started);
+                    (stage, result.map(|(output, _)| output))
+                }
+            }))
+            .await;
+
+            for (stage, result) in results {
+                let output = result.map_err(|e| report(stage, context.providers, e))?;
+                outputs.insert(&stage.id, output);
+            }
+        }
+
+        let stage = &self.config.stages[output[0]];
+        let input = self.input(stage, context, &outputs);
+        let _permit = context.backend.permit().await;
+        let slot = stage.start(context, &input);
+        let calls = ToolCalls::default();
+        let started = Instant::now();
+        let result = tokio::time::timeout(stage.timeout(), stage.stream(&input, context, &calls, tokens))
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|reply| reply);
+        stage.trace(context, slot, input, &result, calls, started);
+        result.map(|(reply, _)| reply).map_err(|e| report(stage, context.providers, e))
+    }
+
+    /// Renders the input of `stage`, leaving out the conversation if the
+    /// prompt would otherwise overflow the stage's context window.
+    fn input(&self, stage: &StageConfig, context: &RunContext<'_>, outputs: &HashMap<&str, String>) -> String {
+        let budget = context.budget.prompt_tokens(stage.settings.agent.context_length);
+        let input = self.render_input(stage, context, outputs, true);
+        let tokens = stage.prompt_tokens(&input);
+        if tokens <= budget {
+            return input;
+        }
+
+        let shortened = self.render_input(stage, context, outputs, false);
+        let shortened_tokens = stage.prompt_tokens(&shortened);
+        if shortened_tokens <= budget {
+            eprintln!(
+                "⚠️  {} prompt is ~{} tokens, over its budget of {}, leaving out the conversation history",
+                stage.id, tokens, budget
+            );
+        } else {
+            eprintln!(
+                "⚠️  {} prompt is ~{} tokens even without the conversation history, over its budget of {}. The model may lose the start of it.",
+                stage.id, shortened_tokens, budget
+            );
+        }
+        shortened
+    }
+
+    fn render_input(
+        &self,
+        stage: &StageConfig,
+        context: &RunContext<'_>,
+        outputs: &HashMap<&str, String>,
+        with_conversation: bool,
+    ) -> String {
+        let conversation = if context.conversation.is_empty() || !with_conversation {
+            String::new()
+        } else {
+            context.templates.conversation.render(&[("history", context.conversation)])
+        };
+        render(&stage.settings.input, |name| match name {
+            PROMPT_VAR => context.prompt.to_string(),
+            CONVERSATION_VAR => conversation.clone(),
+            _ => match outputs.get(name) {
+                Some(output) => truncate(output, context.budget.max_stage_output_tokens),
+                None => templates::global(name),
+            },
+        })
+    }
+}
+
+impl StageConfig {
+    /// Estimated tokens of the preamble and `input` together.
+    fn prompt_tokens(&self, input: &str) -> usize {
+        estimate_tokens(&self.settings.agent.preamble) + estimate_tokens(input)
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_secs(self.settings.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS))
+    }
+
+    async fn complete(
+        &self,
+        input: &str,
+        context: &RunContext<'_>,
+        calls: &ToolCalls,
+    ) -> anyhow::Result<(String, TokenUsage)> {
+        let agent = self.settings.agent.agent(context.providers, self.tools(context, calls));
+        let (response, usage) = agent.prompt_with_usage(input).await?;
+        Ok((filter_think_tag(&response), usage.into()))
+    }
+
+    async fn stream(
+        &self,
+        input: &str,
+        context: &RunContext<'_>,
+        calls: &ToolCalls,
+        tokens: &UnboundedSender<Result<String, AdmeError>>,
+    ) -> anyhow::Result<(String, TokenUsage)> {
+        let agent = self.settings.agent.agent(context.providers, self.tools(context, calls));
+        let mut filter = ThinkFilter::new();
+        let mut reply = String::new();
+        let mut emit = |text: String| {
+            if !text.is_empty() {
+                reply.push_str(&text);
+                // The consumer hanging up only means nobody is watching any more
+                let _ = tokens.send(Ok(text));
+            }
+        };
+
+        let usage = agent.stream(input, |text| emit(filter.push(text))).await?;
+        emit(filter.finish());
+
+        Ok((reply, usage.into()))
+    }
+
+    fn tools(&self, context: &RunContext<'_>, calls: &ToolCalls) -> Vec<Box<dyn ToolDyn>> {
+        self.settings
+            .tools
+            .iter()
+            .map(|tool| -> Box<dyn ToolDyn> {
+                Box::new(TracedTool {
+                    tool: tool.build(
+                        context.memory.clone(),
+                        context.namespace.clone(),
+                        &self.id,
+                        context.budget.max_tool_output_tokens,
+                    ),
+                    calls: calls.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// Adds this stage to the trace of the run before calling the LLM, so it
+    /// shows up even if the run is dropped, and returns where it was put.
+    fn start(&self, context: &RunContext<'_>, input: &str) -> usize {
+        let mut trace = context.trace.lock().unwrap();
+        trace.push(StageTrace::started(&self.id, self.prompt_tokens(input), input));
+        trace.len() - 1
+    }
+
+    /// Fills in how this stage went at `slot` in the trace of the run.
+    fn trace(
+        &self,
+        context: &RunContext<'_>,
+        slot: usize,
+        input: String,
+        result: &anyhow::Result<(String, TokenUsage)>,
+        calls: ToolCalls,
+        started: Instant,
+    ) {
+        let (output, usage) = match result {
+            Ok((output, usage)) => (Some(output.clone()), Some(*usage)),
+            Err(_) => (None, None),
+        };
+        context.trace.lock().unwrap()[slot] = StageTrace {
+            id: self.id.clone(),
+            skipped: false,
+            in_progress: false,
+            prompt_tokens: self.prompt_tokens(&input),
+            input,
+            output,
+            error: result.as_ref().err().map(|e| format!("{:#}", e)),
+            tool_calls: std::mem::take(&mut *calls.lock().unwrap()),
+            usage,
+            duration_ms: started.elapsed().as_millis() as u64,
+        };
+    }
+}
+
+/// Where the pipeline config lives, `ADME_PIPELINE` or `pipeline.json` in the data dir.
+pub fn config_path() -> PathBuf {
+    std::env::var("ADME_PIPELINE")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| data_dir().join("pipeline.json"))
+}
+
+/// Logs the full error chain of a failed stage and classifies it for the user.
+fn report(stage: &StageConfig, providers: &Providers, error: anyhow::Error) -> AdmeError {
+    let provider = stage.settings.agent.provider;
+    let classified = AdmeError::classify(&stage.id, provider, providers.base_url(provider), &error);
+    eprintln!("❌ {}: {:#}", classified, error);
+    classified
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::adme::{
+        memory::{Memory, NewMemory, StoreOutcome},
+        mock::{MockOllama, MockReply},
+        router,
+    };
+
+    fn stage(id: &str, inputs: &[&str], input: &str) -> StageConfig {
+        StageConfig {
+            id: id.to_string(),
+            inputs: inputs.iter().map(|input| input.to_string()).collect(),
+            settings: StageSettings {
+                agent: AgentSettings::new(""),
+                tools: vec![],
+                input: input.to_string(),
+                timeout_secs: None,
+                skip_for_chit_chat: false,
+            },
+        }
+    }
+
+    fn pipeline(stages: Vec<StageConfig>) -> Result<Pipeline, PipelineError> {
+        Pipeline::new(PipelineConfig { stages })
+    }
+
+    #[test]
+    fn test_default_pipeline_is_valid() {
+        let pipeline = Pipeline::new(PipelineConfig::default()).unwrap();
+        assert_eq!(pipeline.levels, vec![vec![0], vec![1]]);
+    }
+
+    #[test]
+    fn test_independent_stages_share_a_level() {
+        let pipeline = pipeline(vec![
+            stage("engineer", &[], "{{prompt}}"),
+            stage("tester", &[], "{{prompt}}"),
+            stage("reviewer", &["engineer", "tester"], "{{engineer}}\n{{tester}}"),
+        ])
+        .unwrap();
+        assert_eq!(pipeline.levels, vec![vec![0, 1], vec![2]]);
+    }
+
+    #[test]
+    fn test_rejects_cycles() {
+        let result = pipeline(vec![
+            stage("a", &["b"], "{{b}}"),
+            stage("b", &["a"], "{{a}}"),
+            stage("c", &["a"], "{{a}}"),
+        ]);
+        assert_eq!(
+            result.err(),
+            Some(PipelineError::Cycle(vec!["a".into(), "b".into(), "c".into()]))
+        );
+    }
+
+    #[test]
+    fn test_rejects_several_outputs() {
+        let result = pipeline(vec![stage("a", &[], "{{prompt}}"), stage("b", &[], "{{prompt}}")]);
+        assert_eq!(result.err(), Some(PipelineError::Outputs(vec!["a".into(), "b".into()])));
+    }
+
+    #[test]
+    fn test_rejects_variables_that_are_not_inputs() {
+        let result = pipeline(vec![stage("a", &[], "{{prompt}}"), stage("b", &["a"], "{{c}}")]);
+        assert_eq!(
+            result.err(),
+            Some(PipelineError::UnknownVariable {
+                stage: "b".into(),
+                name: "c".into()
+            })
+        );
+    }
+
+    #[test]
+    fn test_rejects_preambles_with_unknown_variables() {
+        let mut output = stage("a", &[], "{{prompt}}");
+        output.settings.agent.preamble = String::from("You help {{username}}.");
+        assert!(matches!(
+            pipeline(vec![output]).err(),
+            Some(PipelineError::Preamble(TemplateError::UnknownVariable { name, .. })) if name == "username"
+        ));
+    }
+
+    #[test]
+    fn test_graph_edges_become_inputs() {
+        let graph: PipelineGraph = serde_json::from_value(serde_json::json!({
+            "nodes": [
+                {"id": "node-1", "type": "engineer", "position": {"x": 0, "y": 0},
+                 "data": {"label": "Engineer Agent", "model": "qwen3:30b", "preamble": "", "input": "{{prompt}}"}},
+                {"id": "node-2", "type": "reviewer", "position": {"x": 0, "y": 0},
+                 "data": {"label": "Reviewer Agent", "model": "qwen3:30b", "preamble": "", "input": "{{node-1}}"}}
+            ],
+            "edges": [{"id": "e1", "source": "node-1", "target": "node-2"}]
+        }))
+        .unwrap();
+        let config = PipelineConfig::from(graph);
+        assert_eq!(config.stages[1].inputs, vec!["node-1".to_string()]);
+        assert!(Pipeline::new(config).is_ok());
+    }
+
+    async fn run(mock: &MockOllama, memory: &Arc<Memory>, prompt: &str) -> (String, String, Vec<StageTrace>) {
+        let pipeline = Pipeline::new(PipelineConfig::default()).unwrap();
+        let providers = mock.providers();
+        let trace = Mutex::default();
+        let context = RunContext {
+            prompt,
+            conversation: "",
+            memory,
+            namespace: &Namespace::for_conversation("local", "test"),
+            backend: &Backend::default(),
+            providers: &providers,
+            budget: &BudgetConfig::default(),
+            templates: &Templates::default(),
+            chit_chat: router::is_chit_chat(prompt, None),
+            trace: &trace,
+        };
+
+        let (tokens, mut receiver) = mpsc::unbounded_channel();
+        let reply = pipeline.run(&context, &tokens).await.unwrap();
+        drop(tokens);
+
+        let mut streamed = String::new();
+        while let Some(token) = receiver.recv().await {
+            streamed.push_str(&token.unwrap());
+        }
+        (reply, streamed, trace.into_inner().unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_output_stage_reads_upstream_and_streams() {
+        let mock = MockOllama::start().await;
+        mock.expect("User Prompt: what am I working on", MockReply::text("User is building Seedling."))
+            .expect(
+                "Context: User is building Seedling.",
+                MockReply::text("<think>recall</think>You're building Seedling."),
+            );
+        let memory = Arc::new(Memory::in_memory(MockOllama::memory_config(), mock.agents()));
+
+        let (reply, streamed, _) = run(&mock, &memory, "what am I working on").await;
+
+        assert_eq!(reply, "You're building Seedling.");
+        assert_eq!(streamed, reply);
+        mock.assert_done();
+        // The planner gets the memory tools, the translator doesn't
+        let requests = mock.chat_requests();
+        assert_eq!(requests[0]["tools"].as_array().map(Vec::len), Some(ToolKind::ALL.len()));
+        assert!(requests[1]["tools"].as_array().is_none_or(Vec::is_empty));
+    }
+
+    #[tokio::test]
+    async fn test_planner_tool_calls_reach_memory() {
+        let mock = MockOllama::start().await;
+        mock.reply(MockReply::tool_call(
+            "store_memory",
+            serde_json::json!({"info": "User | name | Sam | Introduction | User explicitly stated"}),
+        ))
+        .reply(MockReply::text("Stored the user's name."))
+        .expect("Context: Stored the user's name.", MockReply::text("Nice to meet you, Sam."));
+        let memory = Arc::new(Memory::in_memory(MockOllama::memory_config(), mock.agents()));
+
+        let (reply, _, trace) = run(&mock, &memory, "I'm Sam").await;
+
+        assert_eq!(reply, "Nice to meet you, Sam.");
+        mock.assert_done();
+        assert_eq!(trace[0].tool_calls.len(), 1);
+        assert_eq!(trace[0].tool_calls[0].name, "store_memory");
+        assert_eq!(trace[1].output.as_deref(), Some("Nice to meet you, Sam."));
+        let stored = memory.list_memories().await;
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].source, "planner");
+    }
+
+    #[tokio::test]
+    async fn test_planner_can_retrieve_then_forget() {
+        let mock = MockOllama::start().await;
+        let memory = Arc::new(Memory::in_memory(MockOllama::memory_config(), mock.agents()));
+        let namespace = Namespace::for_conversation("local", "test");
+        let StoreOutcome::Added(doc) = memory
+            .store_memory(
+                NewMemory {
+                    summary: String::from("User | editor | Vim | Tooling | User explicitly stated"),
+                    tags: vec![],
+                    scope: namespace.default_scope(),
+                    source: String::from("test"),
+                    importance: None,
+                },
+                None,
+            )
+            .await
+            .unwrap()
+        else {
+            panic!("expected a new memory");
+        };
+        mock.reply(MockReply::tool_call("retrieve_memory", serde_json::json!({"info": "User editor"})))
+            .reply(MockReply::tool_call("forget_memory", serde_json::json!({"id": doc.id})))
+            .reply(MockReply::text("Forgot that the user uses Vim."))
+            .expect("Context: Forgot that the user uses Vim.", MockReply::text("Done, I've forgotten that."));
+
+        let (reply, _, trace) = run(&mock, &memory, "I don't use Vim anymore, forget it").await;
+
+        assert_eq!(reply, "Done, I've forgotten that.");
+        mock.assert_done();
+        let calls = trace[0].tool_calls.iter().map(|call| call.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(calls, ["retrieve_memory", "forget_memory"]);
+        assert!(memory.list_memories().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_chit_chat_skips_the_planner() {
+        let mock = MockOllama::start().await;
+        mock.expect("User Prompt: thanks!", MockReply::text("Any time."));
+        let memory = Arc::new(Memory::in_memory(MockOllama::memory_config(), mock.agents()));
+
+        let (reply, _, trace) = run(&mock, &memory, "thanks!").await;
+
+        assert_eq!(reply, "Any time.");
+        mock.assert_done();
+        assert!(trace[0].skipped);
+    }
+
+    #[test]
+    fn test_rejects_skipping_the_output() {
+        let mut output = stage("b", &["a"], "{{a}}");
+        output.settings.skip_for_chit_chat = true;
+        let result = pipeline(vec![stage("a", &[], "{{prompt}}"), output]);
+        assert_eq!(result.err(), Some(PipelineError::SkippedOutput("b".into())));
+    }
+}
@@ -0,0 +1,130 @@
+//! The LLM servers agents can run on.
+//!
+//! Ollama is the default. Anything that speaks the OpenAI chat completions
+//! API, such as the llama-swap server `ProcessManager::_start_llama_swap`
+//! launches, can be used instead by setting `"provider": "openai"` on an
+//! agent. Clients are created once and shared by every agent.
+
+use futures::StreamExt;
+use rig::{
+    agent::{Agent, MultiTurnStreamItem},
+    client::Nothing,
+    completion::{Prompt, Usage},
+    providers::{ollama, openai},
+    streaming::{StreamedAssistantContent, StreamingPrompt},
+};
+use serde::{Deserialize, Serialize};
+
+/// Where Ollama listens unless `OLLAMA_API_BASE_URL` says otherwise.
+pub const DEFAULT_OLLAMA_BASE_URL: &str = "http://localhost:11434";
+/// Where llama-swap listens unless `ADME_OPENAI_BASE_URL` says otherwise.
+pub const DEFAULT_OPENAI_BASE_URL: &str = "http://localhost:8081/v1";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum ProviderKind {
+    #[default]
+    #[serde(rename = "ollama")]
+    Ollama,
+    /// Any OpenAI-compatible endpoint
+    #[serde(rename = "openai")]
+    OpenAi,
+}
+
+pub struct Providers {
+    pub ollama: ollama::Client,
+    pub openai: openai::CompletionsClient,
+    ollama_url: String,
+    openai_url: String,
+}
+
+impl Providers {
+    /// Ollama reads `OLLAMA_API_BASE_URL`, the OpenAI-compatible endpoint
+    /// `ADME_OPENAI_BASE_URL` and `ADME_OPENAI_API_KEY`.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let ollama_url = std::env::var("OLLAMA_API_BASE_URL")
+            .unwrap_or_else(|_| DEFAULT_OLLAMA_BASE_URL.to_string());
+        let openai_url = std::env::var("ADME_OPENAI_BASE_URL")
+            .unwrap_or_else(|_| DEFAULT_OPENAI_BASE_URL.to_string());
+        // Local servers don't check the key but the client insists on one
+        let api_key = std::env::var("ADME_OPENAI_API_KEY").unwrap_or_else(|_| String::from("none"));
+        Self::new(&ollama_url, &openai_url, &api_key)
+    }
+
+    pub fn new(ollama_url: &str, openai_url: &str, openai_api_key: &str) -> anyhow::Result<Self> {
+        Ok(Self {
+            ollama: ollama::Client::builder()
+                .api_key(Nothing)
+                .base_url(ollama_url)
+                .build()?,
+            openai: openai::Client::builder()
+                .api_key(openai_api_key)
+                .base_url(openai_url)
+                .build()?
+                .completions_api(),
+            ollama_url: ollama_url.to_string(),
+            openai_url: openai_url.to_string(),
+        })
+    }
+
+    /// Address the `kind` client sends its requests to.
+    pub fn base_url(&self, kind: ProviderKind) -> &str {
+        match kind {
+            ProviderKind::Ollama => &self.ollama_url,
+            ProviderKind::OpenAi => &self.openai_url,
+        }
+    }
+}
+
+/// An agent on whichever provider its settings asked for.
+pub enum ProviderAgent {
+    Ollama(Agent<ollama::CompletionModel>),
+    OpenAi(Agent<openai::CompletionModel>),
+}
+
+impl ProviderAgent {
+    pub async fn prompt(&self, input: &str) -> anyhow::Result<String> {
+        Ok(self.prompt_with_usage(input).await?.0)
+    }
+
+    /// Like [`prompt`](Self::prompt), along with the tokens used over every turn.
+    pub async fn prompt_with_usage(&self, input: &str) -> anyhow::Result<(String, Usage)> {
+        let response = match self {
+            ProviderAgent::Ollama(agent) => agent.prompt(input).extended_details().await?,
+            ProviderAgent::OpenAi(agent) => agent.prompt(input).extended_details().await?,
+        };
+        Ok((response.output, response.total_usage))
+    }
+
+    /// Prompts the agent, handing each piece of reply text to `on_text` as it
+    /// arrives. Returns the tokens used.
+    pub async fn stream(&self, input: &str, mut on_text: impl FnMut(&str)) -> anyhow::Result<Usage> {
+        let mut usage = Usage::new();
+        match self {
+            ProviderAgent::Ollama(agent) => {
+                let mut stream = agent.stream_prompt(input).await;
+                while let Some(item) = stream.next().await {
+                    match item? {
+                        MultiTurnStreamItem::StreamAssistantItem(StreamedAssistantContent::Text(text)) => {
+                            on_text(&text.text)
+                        }
+                        MultiTurnStreamItem::FinalResponse(response) => usage = response.usage(),
+                        _ => {}
+                    }
+                }
+            }
+            ProviderAgent::OpenAi(agent) => {
+                let mut stream = agent.stream_prompt(input).await;
+                while let Some(item) = stream.next().await {
+                    match item? {
+                        MultiTurnStreamItem::StreamAssistantItem(StreamedAssistantContent::Text(text)) => {
+                            on_text(&text.text)
+                        }
+                        MultiTurnStreamItem::FinalResponse(response) => usage = response.usage(),
+                        _ => {}
+                    }
+                }
+            }
+        }
+        Ok(usage)
+    }
+}
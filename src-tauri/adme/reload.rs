@@ -0,0 +1,73 @@
+//! Picks up edits to the agent and pipeline config files without a restart.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::adme::{agents::Agents, pipeline::Pipeline};
+
+/// Watches the directories holding both config files and reloads whichever changed.
+///
+/// The watcher stops when the returned handle is dropped. A file that fails to
+/// load is reported and the previous config stays in use.
+pub fn watch(
+    agents: Arc<Agents>,
+    pipeline: Arc<RwLock<Arc<Pipeline>>>,
+    pipeline_path: PathBuf,
+) -> anyhow::Result<RecommendedWatcher> {
+    let agents_path = agents.path().map(Path::to_path_buf);
+
+    let mut watcher = {
+        let agents_path = agents_path.clone();
+        let pipeline_path = pipeline_path.clone();
+        notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            let Ok(event) = event else {
+                return;
+            };
+            if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                return;
+            }
+
+            if agents_path.as_deref().is_some_and(|path| touches(&event, path)) {
+                match agents.reload() {
+                    Ok(()) => println!("🔄 Reloaded agent config"),
+                    Err(e) => eprintln!("⚠️  Keeping previous agent config: {:#}", e),
+                }
+            }
+            if touches(&event, &pipeline_path) {
+                match Pipeline::load(&pipeline_path) {
+                    Ok(loaded) => {
+                        *pipeline.write().unwrap() = Arc::new(loaded);
+                        println!("🔄 Reloaded pipeline");
+                    }
+                    Err(e) => eprintln!("⚠️  Keeping previous pipeline: {:#}", e),
+                }
+            }
+        })?
+    };
+
+    // Watch the directories since editors often replace the file instead of writing to it
+    let dirs: HashSet<&Path> = agents_path
+        .iter()
+        .chain(std::iter::once(&pipeline_path))
+        .filter_map(|path| path.parent())
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .collect();
+    for dir in dirs {
+        std::fs::create_dir_all(dir)?;
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    }
+
+    Ok(watcher)
+}
+
+fn touches(event: &notify::Event, path: &Path) -> bool {
+    event
+        .paths
+        .iter()
+        .any(|changed| changed.file_name() == path.file_name())
+}
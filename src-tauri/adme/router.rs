@@ -0,0 +1,77 @@
+//! Spots prompts that are only chit-chat, so the pipeline can skip the stages
+//! that search memory and plan.
+//!
+//! The check is rule-based and costs nothing next to an LLM call. It errs on
+//! the side of running the whole pipeline: a prompt only counts as chit-chat
+//! when every word of it is a greeting, thanks or acknowledgement, and Adme's
+//! last reply didn't ask the user anything. "ok" may well be the answer to
+//! "merge them or keep both?", and that answer needs the planner's tools.
+
+/// Longest prompt, in words, that can still be chit-chat.
+const MAX_WORDS: usize = 6;
+
+/// Words that on their own never need memory or planning.
+const CHIT_CHAT: &[&str] = &[
+    "hi", "hello", "hey", "heya", "hiya", "yo", "morning", "afternoon", "evening", "night", "good",
+    "thanks", "thank", "thx", "ty", "cheers", "ta", "you", "so", "much", "very", "a", "lot",
+    "ok", "okay", "k", "kk", "cool", "great", "nice", "awesome", "perfect", "lovely", "brilliant",
+    "got", "it", "gotcha", "noted", "alright", "np", "lol", "haha", "bye", "goodbye", "later",
+    "see", "ya", "gn", "adme",
+];
+
+/// Whether `prompt` is small talk that the output stage can answer on its
+/// own, given Adme's reply to the previous prompt, if any.
+pub fn is_chit_chat(prompt: &str, last_reply: Option<&str>) -> bool {
+    if last_reply.is_some_and(|reply| reply.contains('?')) {
+        return false;
+    }
+
+    let lower = prompt.to_lowercase();
+    // A question may well be about something Adme remembers
+    if lower.contains('?') {
+        return false;
+    }
+
+    let words: Vec<&str> = lower
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect();
+    // Emoji and punctuation alone, like "👍"
+    if words.is_empty() {
+        return !lower.trim().is_empty();
+    }
+    words.len() <= MAX_WORDS && words.iter().all(|word| CHIT_CHAT.contains(word))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_greetings_and_thanks_are_chit_chat() {
+        for prompt in ["thanks", "Thank you so much!", "hey Adme", "ok cool", "Good night", "👍"] {
+            assert!(is_chit_chat(prompt, None), "{prompt}");
+        }
+        assert!(is_chit_chat("cool", Some("Switched the renderer to Vulkan.")));
+    }
+
+    #[test]
+    fn test_anything_else_runs_the_whole_pipeline() {
+        for prompt in [
+            "thanks, what was the name of that crate?",
+            "hi?",
+            "ok now switch the renderer to Vulkan",
+            "I'm Sam",
+            "",
+            "hey hey hey hey hey hey hey",
+        ] {
+            assert!(!is_chit_chat(prompt, None), "{prompt}");
+        }
+    }
+
+    #[test]
+    fn test_answers_to_a_question_run_the_whole_pipeline() {
+        let asked = "A similar memory already exists. Should I merge them or keep both?";
+        assert!(!is_chit_chat("ok", Some(asked)));
+    }
+}
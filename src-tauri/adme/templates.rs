@@ -0,0 +1,235 @@
+//! Prompt templates with `{{variable}}` placeholders.
+//!
+//! Every prompt Adme puts together in code comes from one of the named
+//! templates below. Each built-in template can be replaced by a `<name>.txt`
+//! file in `ADME_TEMPLATES` (default `templates/` in the data dir), which is
+//! read at startup. Templates are checked as they are loaded, so a misspelt
+//! variable stops Adme starting instead of quietly producing a broken prompt.
+//!
+//! Besides its own variables, every template, and every pipeline stage input,
+//! can use `{{date}}` and `{{user}}`, the user's name from `ADME_USER_NAME`.
+
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+
+use crate::adme::data_dir;
+
+/// Template variable holding today's date.
+pub const DATE_VAR: &str = "date";
+/// Template variable holding the user's name.
+pub const USER_VAR: &str = "user";
+/// Variables every template may use.
+pub const GLOBAL_VARS: [&str; 2] = [DATE_VAR, USER_VAR];
+
+/// How the user is referred to unless `ADME_USER_NAME` is set.
+const DEFAULT_USER_NAME: &str = "User";
+
+#[derive(Debug, PartialEq, thiserror::Error)]
+pub enum TemplateError {
+    #[error("template '{template}' uses '{{{{{name}}}}}', which is not one of {allowed:?}")]
+    UnknownVariable {
+        template: String,
+        name: String,
+        allowed: Vec<String>,
+    },
+    #[error("template '{template}' has an unterminated '{{{{'")]
+    Unterminated { template: String },
+}
+
+/// A template whose placeholders are known to be valid.
+#[derive(Clone, Debug)]
+pub struct Template {
+    text: String,
+}
+
+impl Template {
+    /// Checks that `text` only uses `vars` and the global variables.
+    pub fn new(name: &str, text: &str, vars: &[&str]) -> Result<Self, TemplateError> {
+        let used = variables(text).ok_or_else(|| TemplateError::Unterminated {
+            template: name.to_string(),
+        })?;
+        for used in used {
+            if !vars.contains(&used) && !GLOBAL_VARS.contains(&used) {
+                return Err(TemplateError::UnknownVariable {
+                    template: name.to_string(),
+                    name: used.to_string(),
+                    allowed: vars.iter().chain(&GLOBAL_VARS).map(|var| var.to_string()).collect(),
+                });
+            }
+        }
+        Ok(Self { text: text.to_string() })
+    }
+
+    /// Fills in the template with `values`, plus the date and user name.
+    pub fn render(&self, values: &[(&str, &str)]) -> String {
+        render(&self.text, |name| {
+            values
+                .iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+                .unwrap_or_else(|| global(name))
+        })
+    }
+}
+
+/// Every template in code, loaded once at startup.
+#[derive(Clone, Debug)]
+pub struct Templates {
+    /// How the `{{conversation}}` stage variable presents the history
+    pub conversation: Template,
+    /// One exchange in the history
+    pub turn: Template,
+    /// The summary of exchanges too old to keep in the history
+    pub earlier: Template,
+    /// A finished exchange, for the extractor
+    pub extract: Template,
+    /// A new memory and the similar one it may be merged into, for the combiner
+    pub combine: Template,
+    /// Old turns to fold into the conversation summary, for the summariser
+    pub summarise: Template,
+    /// Stale memories to digest, for the consolidator
+    pub consolidate: Template,
+}
+
+/// Name, built-in text and variables of each template.
+const BUILT_IN: [(&str, &str, &[&str]); 7] = [
+    ("conversation", "Conversation so far:\n{{history}}\n\n", &["history"]),
+    ("turn", "User: {{prompt}}\nAdme: {{response}}", &["prompt", "response"]),
+    ("earlier", "Summary of earlier conversation: {{summary}}", &["summary"]),
+    ("extract", "User Prompt: {{prompt}}\nAgent Response: {{response}}", &["prompt", "response"]),
+    ("combine", "New memory: {{new_memory}}\nCurrent memory: {{current_memory}}", &["new_memory", "current_memory"]),
+    ("summarise", "Current summary: {{summary}}\nExchanges:\n{{exchanges}}", &["summary", "exchanges"]),
+    ("consolidate", "Memories:\n{{memories}}", &["memories"]),
+];
+
+impl Templates {
+    /// Loads the templates in `dir`, using the built-in text for any without a file.
+    pub fn load(dir: &Path) -> anyhow::Result<Self> {
+        let [conversation, turn, earlier, extract, combine, summarise, consolidate] = BUILT_IN.map(|(name, text, vars)| {
+            let path = dir.join(format!("{}.txt", name));
+            let text = match std::fs::read_to_string(&path) {
+                Ok(text) => {
+                    println!("Loaded {} template from {}", name, path.display());
+                    text
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => text.to_string(),
+                Err(e) => return Err(anyhow::Error::from(e).context(format!("reading {}", path.display()))),
+            };
+            Ok(Template::new(name, &text, vars)?)
+        });
+        Ok(Self {
+            conversation: conversation?,
+            turn: turn?,
+            earlier: earlier?,
+            extract: extract?,
+            combine: combine?,
+            summarise: summarise?,
+            consolidate: consolidate?,
+        })
+    }
+}
+
+impl Default for Templates {
+    fn default() -> Self {
+        let [conversation, turn, earlier, extract, combine, summarise, consolidate] = BUILT_IN
+            .map(|(name, text, vars)| Template::new(name, text, vars).expect("built-in templates are valid"));
+        Self {
+            conversation,
+            turn,
+            earlier,
+            extract,
+            combine,
+            summarise,
+            consolidate,
+        }
+    }
+}
+
+/// Where template overrides live, `ADME_TEMPLATES` or `templates/` in the data dir.
+pub fn dir() -> PathBuf {
+    std::env::var("ADME_TEMPLATES")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| data_dir().join("templates"))
+}
+
+/// Value of a global variable, empty for anything else.
+pub fn global(name: &str) -> String {
+    match name {
+        DATE_VAR => Local::now().format("%Y-%m-%d").to_string(),
+        USER_VAR => std::env::var("ADME_USER_NAME").unwrap_or_else(|_| DEFAULT_USER_NAME.to_string()),
+        _ => String::new(),
+    }
+}
+
+/// Names of the `{{variable}}` placeholders in `template`, `None` if one is unterminated.
+pub fn variables(template: &str) -> Option<Vec<&str>> {
+    let mut names = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let end = after.find("}}")?;
+        names.push(after[..end].trim());
+        rest = &after[end + 2..];
+    }
+    Some(names)
+}
+
+/// Replaces every `{{variable}}` in `template` with `value(variable)`.
+pub fn render(template: &str, value: impl Fn(&str) -> String) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            break;
+        };
+        rendered.push_str(&rest[..start]);
+        rendered.push_str(&value(after[..end].trim()));
+        rest = &after[end + 2..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_renders_variables() {
+        let rendered = render("{{ conversation }}User Prompt: {{prompt}}", |name| name.to_uppercase());
+        assert_eq!(rendered, "CONVERSATIONUser Prompt: PROMPT");
+    }
+
+    #[test]
+    fn test_fills_in_values_and_globals() {
+        let template = Template::new("combine", "{{user}}: {{new_memory}}", &["new_memory"]).unwrap();
+        assert_eq!(template.render(&[("new_memory", "likes tea")]), format!("{}: likes tea", global(USER_VAR)));
+    }
+
+    #[test]
+    fn test_rejects_misspelt_variables() {
+        let result = Template::new("combine", "New memory: {{new_memroy}}", &["new_memory"]);
+        assert!(matches!(
+            result,
+            Err(TemplateError::UnknownVariable { name, .. }) if name == "new_memroy"
+        ));
+    }
+
+    #[test]
+    fn test_overrides_are_checked_at_load() {
+        let dir = std::env::temp_dir().join(format!("adme-templates-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("extract.txt"), "Q: {{prompt}}\nA: {{reply}}").unwrap();
+
+        let error = Templates::load(&dir).unwrap_err();
+        assert!(error.to_string().contains("'{{reply}}'"), "{error}");
+
+        std::fs::write(dir.join("extract.txt"), "Q: {{prompt}}\nA: {{response}}").unwrap();
+        let templates = Templates::load(&dir).unwrap();
+        assert_eq!(templates.extract.render(&[("prompt", "hi"), ("response", "hello")]), "Q: hi\nA: hello");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,5 +1,60 @@
+mod forget_memory;
 mod retrieve_memory;
 mod store_memory;
+mod update_memory;
 
+pub use forget_memory::ForgetMemory;
 pub use retrieve_memory::RetrieveMemory;
-pub use store_memory::StoreMemory;
\ No newline at end of file
+pub use store_memory::StoreMemory;
+pub use update_memory::UpdateMemory;
+
+use std::sync::Arc;
+
+use rig::tool::ToolDyn;
+use serde::{Deserialize, Serialize};
+
+use crate::adme::memory::{Memory, Namespace};
+
+/// Names a memory tool in pipeline configuration.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolKind {
+    RetrieveMemory,
+    StoreMemory,
+    UpdateMemory,
+    ForgetMemory,
+}
+
+impl ToolKind {
+    pub const ALL: [ToolKind; 4] = [
+        ToolKind::RetrieveMemory,
+        ToolKind::StoreMemory,
+        ToolKind::UpdateMemory,
+        ToolKind::ForgetMemory,
+    ];
+
+    /// Builds the tool for a stage, recording `source` on anything it writes.
+    ///
+    /// Results that could grow with the store are kept to about `max_tokens`.
+    pub fn build(self, memory: Arc<Memory>, namespace: Namespace, source: &str, max_tokens: usize) -> Box<dyn ToolDyn> {
+        match self {
+            ToolKind::RetrieveMemory => Box::new(RetrieveMemory {
+                memory,
+                namespace,
+                max_tokens,
+            }),
+            ToolKind::StoreMemory => Box::new(StoreMemory {
+                memory,
+                source: source.to_string(),
+                namespace,
+                policy: None,
+            }),
+            ToolKind::UpdateMemory => Box::new(UpdateMemory {
+                memory,
+                source: source.to_string(),
+                namespace,
+            }),
+            ToolKind::ForgetMemory => Box::new(ForgetMemory { memory, namespace }),
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use std::sync::Arc;
+
+use rig::{completion::ToolDefinition, tool::Tool};
+use serde::{Deserialize};
+use serde_json::json;
+
+use crate::adme::memory::{Memory, Namespace};
+
+#[derive(Deserialize)]
+pub struct OperationArgs {
+    id: String
+}
+
+/// Why the memory couldn't be forgotten, with the full error chain.
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct ForgetError(String);
+
+pub struct ForgetMemory {
+    pub memory: Arc<Memory>,
+    /// Only memories visible to this conversation can be forgotten
+    pub namespace: Namespace,
+}
+
+impl Tool for ForgetMemory {
+    const NAME: &'static str = "forget_memory";
+    type Error = ForgetError;
+    type Args = OperationArgs;
+    type Output = String;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: "forget_memory".to_string(),
+            description: "Permanently delete one of your long term memories by its id. Use this when the user says a remembered fact is wrong or asks you to forget it.".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "id": {
+                        "type": "string",
+                        "description": "The id of the memory to forget, as returned by retrieve_memory."
+                    }
+                },
+                "required": ["id"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        // A made-up id is the model's mistake to correct, not a failure of the stage
+        match self.memory.get_memory(&args.id).await {
+            Some(doc) if self.namespace.can_read(&doc.scope) => {}
+            _ => return Ok(format!("No memory with id {} in this conversation", args.id)),
+        }
+
+        match self.memory.delete_memory(&args.id).await {
+            Ok(doc) => Ok(format!("Forgot memory {}: {}", doc.id, doc.summary)),
+            Err(e) => {
+                eprintln!("⚠️  forget_memory failed: {:#}", e);
+                Err(ForgetError(format!("{:#}", e)))
+            }
+        }
+    }
+}
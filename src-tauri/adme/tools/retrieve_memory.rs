@@ -1,40 +1,101 @@
 use std::sync::Arc;
 
+use chrono::{DateTime, Local, NaiveDate, NaiveTime, Utc};
 use rig::{completion::ToolDefinition, tool::Tool};
-use serde::{Deserialize};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 
-use crate::adme::memory::Memory;
+use crate::adme::{
+    budget::{estimate_tokens, truncate},
+    memory::{Memory, MemoryQuery, Namespace},
+};
+
+/// Upper bound on `limit` so a single call can't flood the agent's context.
+const MAX_LIMIT: usize = 10;
 
 #[derive(Deserialize)]
 pub struct OperationArgs {
-    info: String
+    info: String,
+    subject: Option<String>,
+    limit: Option<usize>,
+    min_score: Option<f64>,
+    #[serde(default)]
+    tags: Vec<String>,
+    since: Option<String>,
+    until: Option<String>,
 }
 
+/// Why the memory store couldn't be searched, with the full error chain.
 #[derive(Debug, thiserror::Error)]
-#[error("Lookup error")]
-pub struct LookupError;
+#[error("{0}")]
+pub struct LookupError(String);
+
+/// What the agent gets back, memories or why there aren't any.
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum Retrieved {
+    Memories(Vec<RetrievedMemory>),
+    Note(String),
+}
+
+/// A memory returned to the agent, with the id it needs to update or forget it.
+#[derive(Serialize)]
+pub struct RetrievedMemory {
+    id: String,
+    summary: String,
+    /// How relevant the memory is to the query, between 0 and 1
+    score: f64,
+}
 
 pub struct RetrieveMemory {
-    pub memory: Arc<Memory>
+    pub memory: Arc<Memory>,
+    /// Only memories visible to this conversation are searched
+    pub namespace: Namespace,
+    /// Rough size the results are kept to, see [`fit`]
+    pub max_tokens: usize,
 }
 
 impl Tool for RetrieveMemory {
     const NAME: &'static str = "retrieve_memory";
     type Error = LookupError;
     type Args = OperationArgs;
-    type Output = Vec<String>;
+    type Output = Retrieved;
 
     async fn definition(&self, _prompt: String) -> ToolDefinition {
         ToolDefinition {
             name: "retrieve_memory".to_string(),
-            description: "Search your long term memories of past conversations related to the provided query string. Use this when the user mentions something not in the current conversation.".to_string(),
+            description: "Search your long term memories of past conversations related to the provided query string. Use this when the user mentions something not in the current conversation. Results include a relevance score between 0 and 1.".to_string(),
             parameters: json!({
                 "type": "object",
                 "properties": {
                     "info": {
                         "type": "string",
-                        "description": "The search term or topic to remember."
+                        "description": "The search term or topic to remember. Exact names, IDs and project codes are matched by keyword as well as meaning."
+                    },
+                    "subject": {
+                        "type": "string",
+                        "description": "Only return atomic truths about this exact subject, e.g. 'User'."
+                    },
+                    "limit": {
+                        "type": "integer",
+                        "description": format!("Maximum number of memories to return, at most {}. Defaults to 2.", MAX_LIMIT)
+                    },
+                    "min_score": {
+                        "type": "number",
+                        "description": "Drop memories whose relevance to the query, by meaning and keywords, is below this value between 0 and 1. Recency and importance don't count towards it."
+                    },
+                    "tags": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Only return memories carrying all of these tags."
+                    },
+                    "since": {
+                        "type": "string",
+                        "description": "Only return memories updated at or after this RFC 3339 timestamp or YYYY-MM-DD date."
+                    },
+                    "until": {
+                        "type": "string",
+                        "description": "Only return memories updated at or before this RFC 3339 timestamp or YYYY-MM-DD date, which includes the whole day."
                     }
                 },
                 "required": ["info"]
@@ -44,10 +105,135 @@ impl Tool for RetrieveMemory {
 
     async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
         println!("Retrieving memory for query: {}", args.info);
-        let result = match self.memory.n_closest_memories(&args.info, 2).await {
+
+        let mut query = MemoryQuery::new(args.info);
+        query.subject = args.subject;
+        query.limit = args.limit.unwrap_or(query.limit).min(MAX_LIMIT);
+        query.min_score = args.min_score.unwrap_or(query.min_score);
+        query.tags = args.tags;
+        for (value, bound, end_of_day) in [(args.since, &mut query.since, false), (args.until, &mut query.until, true)] {
+            let Some(value) = value else {
+                continue;
+            };
+            match parse_time(&value, end_of_day) {
+                Some(time) => *bound = Some(time),
+                None => {
+                    return Ok(Retrieved::Note(format!(
+                        "Nothing retrieved. '{}' is not an RFC 3339 timestamp or a YYYY-MM-DD date.",
+                        value
+                    )));
+                }
+            }
+        }
+        query.scopes = self.namespace.readable();
+
+        let result = match self.memory.n_closest_memories(&query).await {
             Ok(res) => res,
-            Err(_) => return Err(LookupError),
+            Err(e) => {
+                eprintln!("⚠️  retrieve_memory failed: {:#}", e);
+                return Err(LookupError(format!("{:#}", e)));
+            }
         };
-        Ok(result.iter().map(|f| {f.2.summary.clone()}).collect::<Vec<_>>())
+        let retrieved = fit(
+            result
+                .into_iter()
+                .map(|f| RetrievedMemory {
+                    id: f.doc.id,
+                    summary: f.doc.summary,
+                    score: f.score,
+                })
+                .collect(),
+            self.max_tokens,
+        );
+
+        let ids = retrieved.iter().map(|memory| memory.id.clone()).collect::<Vec<_>>();
+        if let Err(e) = self.memory.mark_accessed(&ids).await {
+            eprintln!("⚠️  Failed to record memory access: {}", e);
+        }
+
+        Ok(Retrieved::Memories(retrieved))
+    }
+}
+
+/// Reads an RFC 3339 timestamp, or a plain date in local time, taken as its
+/// first moment or, with `end_of_day`, its last.
+fn parse_time(value: &str, end_of_day: bool) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Some(time.with_timezone(&Utc));
+    }
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()?;
+    let time = if end_of_day {
+        NaiveTime::from_hms_milli_opt(23, 59, 59, 999)?
+    } else {
+        NaiveTime::MIN
+    };
+    let local = date.and_time(time).and_local_timezone(Local);
+    let local = if end_of_day { local.latest() } else { local.earliest() }?;
+    Some(local.with_timezone(&Utc))
+}
+
+/// Keeps the best ranked of `memories` that fit in about `max_tokens` once
+/// serialized, shortening the summary of the best one if it doesn't fit alone.
+fn fit(memories: Vec<RetrievedMemory>, max_tokens: usize) -> Vec<RetrievedMemory> {
+    let tokens = |memory: &RetrievedMemory| serde_json::to_string(memory).map_or(0, |json| estimate_tokens(&json));
+
+    let mut used = 0;
+    let mut kept = Vec::new();
+    for mut memory in memories {
+        let needed = tokens(&memory);
+        if used + needed > max_tokens {
+            if kept.is_empty() {
+                let overhead = needed - estimate_tokens(&memory.summary);
+                memory.summary = truncate(&memory.summary, max_tokens.saturating_sub(overhead));
+                kept.push(memory);
+            }
+            break;
+        }
+        used += needed;
+        kept.push(memory);
+    }
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn retrieved(summary: &str, score: f64) -> RetrievedMemory {
+        RetrievedMemory {
+            id: String::from("4f0c"),
+            summary: summary.to_string(),
+            score,
+        }
+    }
+
+    #[test]
+    fn test_dates_and_timestamps_are_accepted() {
+        assert_eq!(
+            parse_time("2025-03-01T12:00:00Z", false),
+            Some("2025-03-01T12:00:00Z".parse().unwrap())
+        );
+        let since = parse_time("2025-03-01", false).unwrap();
+        let until = parse_time("2025-03-01", true).unwrap();
+        assert_eq!(until - since, chrono::Duration::milliseconds(24 * 60 * 60 * 1000 - 1));
+        assert_eq!(parse_time("last week", false), None);
+    }
+
+    #[test]
+    fn test_lower_ranked_memories_are_dropped_to_fit() {
+        let memories = vec![retrieved(&"a".repeat(200), 0.9), retrieved(&"b".repeat(200), 0.8)];
+        let kept = fit(memories, 80);
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].score, 0.9);
+    }
+
+    #[test]
+    fn test_a_memory_too_long_on_its_own_is_shortened() {
+        let kept = fit(vec![retrieved(&"a".repeat(1000), 0.9)], 50);
+        assert_eq!(kept.len(), 1);
+        assert!(kept[0].summary.starts_with("aaaa"));
+        assert!(kept[0].summary.contains("more tokens left out"));
+        assert!(estimate_tokens(&serde_json::to_string(&kept[0]).unwrap()) <= 70);
     }
 }
\ No newline at end of file
@@ -4,26 +4,36 @@ use rig::{completion::ToolDefinition, tool::Tool};
 use serde::{Deserialize};
 use serde_json::json;
 
-use crate::adme::memory::Memory;
+use crate::adme::memory::{Memory, MergePolicy, Namespace, NewMemory, ScopeKind, StoreOutcome};
 
 #[derive(Deserialize)]
 pub struct OperationArgs {
-    info: String
+    info: String,
+    #[serde(default)]
+    tags: Vec<String>,
+    scope: Option<ScopeKind>,
+    importance: Option<f64>,
 }
 
+/// Why the memory couldn't be stored, with the full error chain.
 #[derive(Debug, thiserror::Error)]
-#[error("Lookup error")]
-pub struct LookupError;
+#[error("{0}")]
+pub struct StoreError(String);
 
 pub struct StoreMemory {
-    pub memory: Arc<Memory>
+    pub memory: Arc<Memory>,
+    /// Recorded as the source of every memory this tool stores
+    pub source: String,
+    pub namespace: Namespace,
+    /// Used instead of the configured merge policy, by callers with no user to ask
+    pub policy: Option<MergePolicy>,
 }
 
 impl Tool for StoreMemory {
     const NAME: &'static str = "store_memory";
-    type Error = LookupError;
+    type Error = StoreError;
     type Args = OperationArgs;
-    type Output = ();
+    type Output = String;
 
     async fn definition(&self, _prompt: String) -> ToolDefinition {
         ToolDefinition {
@@ -34,7 +44,21 @@ impl Tool for StoreMemory {
                 "properties": {
                     "info": {
                         "type": "string",
-                        "description": "The information to store in long term memory."
+                        "description": "The information to store in long term memory, preferably as an atomic truth: Subject | Attribute | Value | Context | Rationale."
+                    },
+                    "tags": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Optional labels to find this memory by later, e.g. a project code."
+                    },
+                    "scope": {
+                        "type": "string",
+                        "enum": ["user", "chat", "project", "global"],
+                        "description": "Who may recall this memory. Defaults to 'user' for facts about the person you are talking to; use 'global' only for facts useful to everyone."
+                    },
+                    "importance": {
+                        "type": "number",
+                        "description": "How much this memory matters, from 0 (trivia) to 1 (core fact about the user or project). Defaults to 0.5."
                     }
                 },
                 "required": ["info"]
@@ -43,10 +67,34 @@ impl Tool for StoreMemory {
     }
 
     async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
-        let result = self.memory.store_memory(&args.info).await;
+        let scope = match args.scope {
+            Some(kind) => match self.namespace.resolve(kind) {
+                Some(scope) => scope,
+                None => return Ok(format!("Not stored. This conversation has no {:?} scope.", kind)),
+            },
+            None => self.namespace.default_scope(),
+        };
+
+        let new = NewMemory {
+            summary: args.info,
+            tags: args.tags,
+            scope,
+            source: self.source.clone(),
+            importance: args.importance,
+        };
+
+        let result = self.memory.store_memory(new, self.policy).await;
         match result {
-            Ok(_) => Ok(()),
-            Err(_) => Err(LookupError)
+            Ok(StoreOutcome::Added(doc)) => Ok(format!("Stored new memory {}", doc.id)),
+            Ok(StoreOutcome::Merged(doc)) => Ok(format!("Merged into memory {}: {}", doc.id, doc.summary)),
+            Ok(StoreOutcome::NeedsConfirmation { existing }) => Ok(format!(
+                "Not stored. A similar memory already exists ({}: {}). Ask the user whether it should change, and if so call update_memory with its id.",
+                existing.id, existing.summary
+            )),
+            Err(e) => {
+                eprintln!("⚠️  store_memory failed: {:#}", e);
+                Err(StoreError(format!("{:#}", e)))
+            }
         }
     }
 }
\ No newline at end of file
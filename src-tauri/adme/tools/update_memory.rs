@@ -0,0 +1,71 @@
+use std::sync::Arc;
+
+use rig::{completion::ToolDefinition, tool::Tool};
+use serde::{Deserialize};
+use serde_json::json;
+
+use crate::adme::memory::{Memory, Namespace};
+
+#[derive(Deserialize)]
+pub struct OperationArgs {
+    id: String,
+    info: String
+}
+
+/// Why the memory couldn't be updated, with the full error chain.
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct UpdateError(String);
+
+pub struct UpdateMemory {
+    pub memory: Arc<Memory>,
+    /// Recorded as the source of every memory this tool rewrites
+    pub source: String,
+    /// Only memories visible to this conversation can be updated
+    pub namespace: Namespace,
+}
+
+impl Tool for UpdateMemory {
+    const NAME: &'static str = "update_memory";
+    type Error = UpdateError;
+    type Args = OperationArgs;
+    type Output = String;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: "update_memory".to_string(),
+            description: "Replace the content of one of your long term memories by its id. Use this when the user corrects a remembered fact.".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "id": {
+                        "type": "string",
+                        "description": "The id of the memory to update, as returned by retrieve_memory."
+                    },
+                    "info": {
+                        "type": "string",
+                        "description": "The corrected information that should replace the memory."
+                    }
+                },
+                "required": ["id", "info"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        // A made-up id is the model's mistake to correct, not a failure of the stage
+        match self.memory.get_memory(&args.id).await {
+            Some(doc) if self.namespace.can_read(&doc.scope) => {}
+            _ => return Ok(format!("No memory with id {} in this conversation", args.id)),
+        }
+
+        let result = self.memory.update_memory(&args.id, &args.info, &self.source).await;
+        match result {
+            Ok(doc) => Ok(format!("Updated memory {}: {}", doc.id, doc.summary)),
+            Err(e) => {
+                eprintln!("⚠️  update_memory failed: {:#}", e);
+                Err(UpdateError(format!("{:#}", e)))
+            }
+        }
+    }
+}
@@ -0,0 +1,327 @@
+//! A record of what happened while Adme answered a prompt.
+//!
+//! Each request leaves a [`Trace`] of the stages that ran, what they were
+//! asked, what they answered, the memory tools they called and how long it
+//! all took. Traces are appended to `traces.jsonl` in the data dir, and only
+//! the most recent [`MAX_TRACES`] are kept. The file is cut back down to them
+//! once it has grown by another [`COMPACT_EVERY`].
+
+use std::{
+    collections::VecDeque,
+    future::Future,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use chrono::{DateTime, Utc};
+use rig::{
+    completion::{ToolDefinition, Usage},
+    tool::{ToolDyn, ToolError},
+};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+
+/// Traces kept on disk and in memory.
+pub const MAX_TRACES: usize = 200;
+/// Traces appended beyond [`MAX_TRACES`] before the file is rewritten.
+pub const COMPACT_EVERY: usize = 50;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Trace {
+    pub id: String,
+    /// Chat the prompt came from
+    pub chat: String,
+    pub started_at: DateTime<Utc>,
+    pub prompt: String,
+    /// Stages in the order they started
+    pub stages: Vec<StageTrace>,
+    /// What the user was told went wrong, `None` if the reply completed
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+impl Trace {
+    pub fn new(chat: &str, prompt: &str) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            chat: chat.to_string(),
+            started_at: Utc::now(),
+            prompt: prompt.to_string(),
+            stages: Vec::new(),
+            error: None,
+            duration_ms: 0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StageTrace {
+    pub id: String,
+    /// Left out by the router, nothing else below is set
+    #[serde(default)]
+    pub skipped: bool,
+    /// Still running when the trace was recorded, e.g. because the request
+    /// was cancelled; only the input is set
+    #[serde(default)]
+    pub in_progress: bool,
+    /// Estimated tokens of the preamble and input, see [`estimate_tokens`](super::budget::estimate_tokens)
+    #[serde(default)]
+    pub prompt_tokens: usize,
+    pub input: String,
+    pub output: Option<String>,
+    /// The full error chain if the stage failed
+    pub error: Option<String>,
+    pub tool_calls: Vec<ToolCallTrace>,
+    /// As reported by the provider, `None` if the stage never finished
+    pub usage: Option<TokenUsage>,
+    /// Time spent on the LLM call, not counting time queued for the backend
+    pub duration_ms: u64,
+}
+
+impl StageTrace {
+    pub fn skipped(id: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            skipped: true,
+            in_progress: false,
+            prompt_tokens: 0,
+            input: String::new(),
+            output: None,
+            error: None,
+            tool_calls: Vec::new(),
+            usage: None,
+            duration_ms: 0,
+        }
+    }
+
+    /// A stage that has been given `input` and not answered yet.
+    pub fn started(id: &str, prompt_tokens: usize, input: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            skipped: false,
+            in_progress: true,
+            prompt_tokens,
+            input: input.to_string(),
+            output: None,
+            error: None,
+            tool_calls: Vec::new(),
+            usage: None,
+            duration_ms: 0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct TokenUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+impl From<Usage> for TokenUsage {
+    fn from(usage: Usage) -> Self {
+        Self {
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ToolCallTrace {
+    pub name: String,
+    /// JSON arguments exactly as the model sent them
+    pub arguments: String,
+    pub result: Option<String>,
+    pub error: Option<String>,
+    pub duration_ms: u64,
+}
+
+/// Tool calls made by one stage, filled in by its [`TracedTool`]s.
+pub type ToolCalls = Arc<Mutex<Vec<ToolCallTrace>>>;
+
+/// Wraps a tool so every call to it is added to `calls`.
+pub struct TracedTool {
+    pub tool: Box<dyn ToolDyn>,
+    pub calls: ToolCalls,
+}
+
+impl ToolDyn for TracedTool {
+    fn name(&self) -> String {
+        self.tool.name()
+    }
+
+    fn definition<'a>(&'a self, prompt: String) -> Pin<Box<dyn Future<Output = ToolDefinition> + Send + 'a>> {
+        self.tool.definition(prompt)
+    }
+
+    fn call<'a>(&'a self, args: String) -> Pin<Box<dyn Future<Output = Result<String, ToolError>> + Send + 'a>> {
+        Box::pin(async move {
+            let started = Instant::now();
+            let result = self.tool.call(args.clone()).await;
+            self.calls.lock().unwrap().push(ToolCallTrace {
+                name: self.tool.name(),
+                arguments: args,
+                result: result.as_ref().ok().cloned(),
+                error: result.as_ref().err().map(ToString::to_string),
+                duration_ms: started.elapsed().as_millis() as u64,
+            });
+            result
+        })
+    }
+}
+
+/// The most recent traces, written through to a JSON lines file.
+pub struct Traces {
+    recent: tokio::sync::Mutex<Recent>,
+    /// `None` keeps traces in memory only
+    path: Option<PathBuf>,
+}
+
+#[derive(Default)]
+struct Recent {
+    traces: VecDeque<Trace>,
+    /// Traces in the file, including ones already dropped from `traces`
+    on_disk: usize,
+}
+
+impl Traces {
+    /// Loads the traces kept at `path`, dropping all but the newest [`MAX_TRACES`].
+    pub fn open(path: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let path = path.into();
+        let mut traces = load(&path)?;
+        let on_disk = traces.len();
+        if traces.len() > MAX_TRACES {
+            traces.drain(..traces.len() - MAX_TRACES);
+        }
+        Ok(Self {
+            recent: tokio::sync::Mutex::new(Recent { traces, on_disk }),
+            path: Some(path),
+        })
+    }
+
+    #[cfg(test)]
+    pub fn in_memory() -> Self {
+        Self {
+            recent: tokio::sync::Mutex::default(),
+            path: None,
+        }
+    }
+
+    pub async fn record(&self, trace: Trace) -> anyhow::Result<()> {
+        let mut recent = self.recent.lock().await;
+        recent.traces.push_back(trace);
+        if recent.traces.len() > MAX_TRACES {
+            recent.traces.pop_front();
+        }
+
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        if recent.on_disk >= MAX_TRACES + COMPACT_EVERY {
+            // Write to a sibling file first so a crash mid-write never loses the traces
+            let tmp_path = path.with_extension("jsonl.tmp");
+            tokio::fs::write(&tmp_path, to_lines(&recent.traces)?).await?;
+            tokio::fs::rename(&tmp_path, path).await?;
+            recent.on_disk = recent.traces.len();
+        } else {
+            let mut line = serde_json::to_string(recent.traces.back().expect("just pushed"))?;
+            line.push('\n');
+            let mut file = tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .await?;
+            file.write_all(line.as_bytes()).await?;
+            recent.on_disk += 1;
+        }
+        Ok(())
+    }
+
+    /// Up to `limit` traces, newest first, optionally only those from `chat`.
+    pub async fn recent(&self, chat: Option<&str>, limit: usize) -> Vec<Trace> {
+        self.recent
+            .lock()
+            .await
+            .traces
+            .iter()
+            .rev()
+            .filter(|trace| chat.is_none_or(|chat| trace.chat == chat))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    pub async fn get(&self, id: &str) -> Option<Trace> {
+        self.recent.lock().await.traces.iter().find(|trace| trace.id == id).cloned()
+    }
+}
+
+fn load(path: &Path) -> anyhow::Result<VecDeque<Trace>> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(VecDeque::new()),
+        Err(e) => return Err(e.into()),
+    };
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| Ok(serde_json::from_str(line)?))
+        .collect()
+}
+
+fn to_lines<'a>(traces: impl IntoIterator<Item = &'a Trace>) -> anyhow::Result<String> {
+    let mut contents = String::new();
+    for trace in traces {
+        contents.push_str(&serde_json::to_string(trace)?);
+        contents.push('\n');
+    }
+    Ok(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_keeps_only_the_newest_traces() {
+        let traces = Traces::in_memory();
+        for i in 0..MAX_TRACES + 5 {
+            let chat = if i % 2 == 0 { "even" } else { "odd" };
+            traces.record(Trace::new(chat, &i.to_string())).await.unwrap();
+        }
+
+        let newest = traces.recent(None, usize::MAX).await;
+        assert_eq!(newest.len(), MAX_TRACES);
+        assert_eq!(newest[0].prompt, (MAX_TRACES + 4).to_string());
+
+        let odd = traces.recent(Some("odd"), 1).await;
+        assert_eq!(odd[0].prompt, (MAX_TRACES + 3).to_string());
+        assert!(traces.get(&odd[0].id).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_file_is_compacted_every_so_often() {
+        let path = std::env::temp_dir().join(format!("adme-traces-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let lines = |path: &Path| std::fs::read_to_string(path).unwrap().lines().count();
+
+        let traces = Traces::open(&path).unwrap();
+        for i in 0..MAX_TRACES + COMPACT_EVERY {
+            traces.record(Trace::new("chat", &i.to_string())).await.unwrap();
+        }
+        assert_eq!(lines(&path), MAX_TRACES + COMPACT_EVERY);
+
+        traces.record(Trace::new("chat", "compacted")).await.unwrap();
+        assert_eq!(lines(&path), MAX_TRACES);
+
+        let reopened = Traces::open(&path).unwrap();
+        assert_eq!(reopened.recent(None, 1).await[0].prompt, "compacted");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -8,7 +8,7 @@ use tokio::sync::Mutex;
 // Import types we need
 pub use terminal::TerminalState;
 
-use crate::{adme::Adme, hardware, process, telegram, terminal};
+use crate::{adme::{self, Adme}, hardware, process, telegram, terminal};
 
 /// Application state for managing seedling's core components
 pub struct AppState {
@@ -78,7 +78,19 @@ pub fn run() {
         .manage(terminal_state.clone())
         .invoke_handler(tauri::generate_handler![
             terminal::write_to_buffer,
-            terminal::resize_pty
+            terminal::resize_pty,
+            adme::commands::list_memories,
+            adme::commands::get_memory,
+            adme::commands::update_memory,
+            adme::commands::delete_memory,
+            adme::commands::rollback_memory,
+            adme::commands::export_memories,
+            adme::commands::import_memories,
+            adme::commands::consolidate_memories,
+            adme::commands::get_pipeline,
+            adme::commands::set_pipeline,
+            adme::commands::list_traces,
+            adme::commands::get_trace
         ])
         .setup(|app| {
             println!("🚀 Initializing Tauri application...");
@@ -97,6 +109,14 @@ pub fn run() {
                     eprintln!("❌ Application initialization failed: {}", e);
                     return;
                 };
+                // Re-embedding needs the backend `init` just started
+                let memory = adme.memory();
+                tauri::async_runtime::spawn(async move {
+                    if let Err(e) = memory.migrate_embeddings().await {
+                        eprintln!("⚠️  Failed to re-embed memories: {}", e);
+                    }
+                    memory.consolidate_periodically().await;
+                });
                 telegram::start(adme.clone()).await;
                 // terminal::start(handle, terminal.clone());
             });
@@ -105,8 +125,12 @@ pub fn run() {
         .on_window_event(|window, event| {
             if let tauri::WindowEvent::Destroyed = event {
                 let app_state = window.state::<ManagedState>().inner().clone();
+                let memory = window.state::<Adme>().memory();
 
                 tauri::async_runtime::block_on(async move {
+                    if let Err(e) = memory.flush().await {
+                        eprintln!("❌ Failed to save memories during shutdown: {}", e);
+                    }
                     let mut manager = app_state.process_manager.lock().await;
                     if let Err(e) = manager.stop_all().await {
                         eprintln!("❌ Failed to stop processes during shutdown: {}", e);
@@ -0,0 +1,67 @@
+//! Headless command line entry points that run without starting the desktop app.
+
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+use crate::adme::Adme;
+
+#[derive(Parser)]
+#[command(name = "seedling", about = "AIDME AI development management environment")]
+pub struct Cli {
+    /// Runs a headless command instead of the desktop app
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Manage Adme's long term memory
+    Memory {
+        #[command(subcommand)]
+        action: MemoryCommand,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum MemoryCommand {
+    /// Export every memory as JSONL
+    Export {
+        path: PathBuf,
+        /// Include embeddings so the import can skip re-embedding
+        #[arg(long)]
+        embeddings: bool,
+    },
+    /// Import memories from a JSONL export, replacing any with the same ID
+    Import { path: PathBuf },
+    /// Re-embed memories stored with a different embedding model than the configured one
+    Reembed,
+}
+
+/// Runs a headless command to completion.
+///
+/// Refuses to run while the app is open, since it would overwrite the memory
+/// store with its own copy the next time it saves.
+pub fn run(command: Command) -> anyhow::Result<()> {
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async {
+        let adme = Adme::open()?;
+
+        match command {
+            Command::Memory { action } => match action {
+                MemoryCommand::Export { path, embeddings } => {
+                    adme.memory().export_jsonl(&path, embeddings).await?;
+                }
+                MemoryCommand::Import { path } => {
+                    adme.memory().import_jsonl(&path).await?;
+                }
+                MemoryCommand::Reembed => {
+                    let count = adme.memory().migrate_embeddings().await?;
+                    println!("Re-embedded {} memories", count);
+                }
+            },
+        }
+
+        Ok(())
+    })
+}
@@ -6,3 +6,127 @@ pub fn filter_think_tag(input: &str) -> String {
     }
     reply
 }
+
+
+const THINK_OPEN: &str = "<think>";
+const THINK_CLOSE: &str = "</think>";
+
+enum ThinkState {
+    /// No `<think>` yet, but the chat template may have opened the block itself
+    Start,
+    Thinking,
+    Answer,
+}
+
+/// Incremental [`filter_think_tag`] for streamed replies.
+///
+/// Feed chunks in with [`ThinkFilter::push`], which returns the part that is
+/// safe to show. Text is held back while it could still turn out to be part
+/// of a `<think>` block, which for a reply that doesn't open with `<think>`
+/// means until a `</think>` shows up or the stream ends.
+pub struct ThinkFilter {
+    state: ThinkState,
+    pending: String,
+}
+
+impl ThinkFilter {
+    pub fn new() -> Self {
+        Self {
+            state: ThinkState::Start,
+            pending: String::new(),
+        }
+    }
+
+    pub fn push(&mut self, chunk: &str) -> String {
+        match self.state {
+            ThinkState::Answer => chunk.to_string(),
+            ThinkState::Start => {
+                self.pending.push_str(chunk);
+                let trimmed = self.pending.trim_start();
+                if let Some(rest) = trimmed.strip_prefix(THINK_OPEN) {
+                    self.pending = rest.to_string();
+                    self.state = ThinkState::Thinking;
+                    self.push("")
+                } else if let Some(index) = self.pending.find(THINK_CLOSE) {
+                    let answer = self.pending[index + THINK_CLOSE.len()..].to_string();
+                    self.pending.clear();
+                    self.state = ThinkState::Answer;
+                    answer
+                } else {
+                    String::new()
+                }
+            }
+            ThinkState::Thinking => {
+                self.pending.push_str(chunk);
+                if let Some(index) = self.pending.find(THINK_CLOSE) {
+                    let answer = self.pending[index + THINK_CLOSE.len()..].to_string();
+                    self.pending.clear();
+                    self.state = ThinkState::Answer;
+                    return answer;
+                }
+                // Only a tail that could still be the start of the closing tag matters
+                let mut keep_from = self.pending.len().saturating_sub(THINK_CLOSE.len() - 1);
+                while !self.pending.is_char_boundary(keep_from) {
+                    keep_from -= 1;
+                }
+                self.pending.drain(..keep_from);
+                String::new()
+            }
+        }
+    }
+
+    /// Releases anything still held back once the stream has ended.
+    pub fn finish(&mut self) -> String {
+        match self.state {
+            ThinkState::Start => std::mem::take(&mut self.pending),
+            // An unterminated think block never reached an answer
+            ThinkState::Thinking | ThinkState::Answer => String::new(),
+        }
+    }
+}
+
+impl Default for ThinkFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(chunks: &[&str]) -> String {
+        let mut filter = ThinkFilter::new();
+        let mut out: String = chunks.iter().map(|chunk| filter.push(chunk)).collect();
+        out.push_str(&filter.finish());
+        out
+    }
+
+    #[test]
+    fn test_strips_think_block_split_across_chunks() {
+        assert_eq!(stream(&["<thi", "nk>plan", "ning</th", "ink>Hello", " there"]), "Hello there");
+    }
+
+    #[test]
+    fn test_passes_plain_replies_through() {
+        assert_eq!(stream(&["Hel", "lo"]), "Hello");
+    }
+
+    #[test]
+    fn test_releases_short_reply_that_looked_like_a_tag() {
+        assert_eq!(stream(&["<th"]), "<th");
+    }
+
+    #[test]
+    fn test_drops_thinking_opened_by_the_template() {
+        let chunks = ["plan", "ning</th", "ink>\n\nHello"];
+        assert_eq!(stream(&chunks), "\n\nHello");
+        assert_eq!(stream(&chunks), filter_think_tag(&chunks.concat()));
+    }
+
+    #[test]
+    fn test_matches_filter_think_tag() {
+        let reply = "<think>\nhmm\n</think>\n\nSure.";
+        assert_eq!(stream(&[reply]), filter_think_tag(reply));
+    }
+}
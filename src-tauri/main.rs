@@ -1,7 +1,9 @@
 //! Main entry point for the AIDME AI development management environment.
 
+use clap::Parser;
 use dotenvy::dotenv;
 mod app;
+mod cli;
 mod terminal;
 mod hardware;
 mod process;
@@ -12,6 +14,20 @@ mod adme;
 // Run the application
 fn main() {
     dotenv().expect("Failed to load .env file. Please ensure it exists and is properly configured.");
+    // Seedling's own logs show by default, RUST_LOG overrides
+    pretty_env_logger::formatted_builder()
+        .filter_module("seedling", log::LevelFilter::Info)
+        .parse_default_env()
+        .init();
+
+    // Headless commands don't need the bot or the desktop app
+    if let Some(command) = cli::Cli::parse().command {
+        if let Err(e) = cli::run(command) {
+            eprintln!("❌ {:#}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
 
     std::env::var("TELOXIDE_TOKEN").expect("TELOXIDE_TOKEN not set in .env file");
     std::env::var("OLLAMA_API_BASE_URL").expect("OLLAMA_API_BASE_URL not set in .env file");
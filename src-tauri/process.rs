@@ -4,6 +4,7 @@
 //! is properly started, monitored, and terminated when the application dies.
 
 use anyhow::Result;
+use crate::adme::DEFAULT_CONTEXT_LENGTH;
 use std::collections::HashMap;
 use std::{
     process::Command,
@@ -77,7 +78,7 @@ impl ProcessManager {
             .arg("serve")
             .env("OLLAMA_KV_CACHE_TYPE", "q4_0")
             .env("OLLAMA_FLASH_ATTENTION", "1")
-            .env("OLLAMA_CONTEXT_LENGTH", "24576")
+            .env("OLLAMA_CONTEXT_LENGTH", DEFAULT_CONTEXT_LENGTH.to_string())
             .kill_on_drop(true)
             .spawn()
             .map_err(|e| format!("Failed to start ollama: {}", e))?;
@@ -1,11 +1,16 @@
-use crate::{
-    adme::{Adme},
-    filters::filter_think_tag,
-};
-use teloxide::prelude::*;
+use std::time::{Duration, Instant};
+
+use crate::adme::{Adme, Namespace, Trace};
+use teloxide::{prelude::*, types::MessageId};
+
+/// Telegram rate limits message edits, so partial replies are only pushed this often.
+const EDIT_INTERVAL: Duration = Duration::from_secs(1);
+/// Characters of each stage input, output and tool result shown by `/trace`.
+const TRACE_FIELD_CHARS: usize = 300;
+/// Telegram refuses messages longer than 4096 characters.
+const MAX_MESSAGE_CHARS: usize = 4000;
 
 pub async fn start(agent: Adme) {
-    pretty_env_logger::init();
     let bot = Bot::from_env();
 
     teloxide::repl(bot, move |bot: Bot, msg: Message| {
@@ -19,15 +24,47 @@ pub async fn start(agent: Adme) {
             } else {
                 let input = msg.text().unwrap_or("hello").to_string();
 
-                if input.eq_ignore_ascii_case("/start") {
+                if is_command(&input, "start") {
                     bot.send_message(msg.chat.id, "Welcome to the bot!").await?;
                     return Ok(());
                 }
 
-                let mut reply = agent.prompt(&input).await;
-                reply = filter_think_tag(&reply);
-                bot.send_message(msg.chat.id, &reply).await?;
+                // Telegram always sets a sender for validated users
+                let user_id = msg.from.as_ref().map(|user| user.id.0).unwrap_or_default();
+                let namespace = Namespace::for_conversation(user_id, msg.chat.id);
+
+                if is_command(&input, "reset") {
+                    agent.reset_history(&namespace).await;
+                    bot.send_message(msg.chat.id, "Conversation history cleared.").await?;
+                    return Ok(());
+                }
+
+                if is_command(&input, "trace") {
+                    let reply = match agent.last_trace(&namespace).await {
+                        Some(trace) => describe_trace(&trace),
+                        None => String::from("Nothing traced in this chat yet."),
+                    };
+                    bot.send_message(msg.chat.id, reply).await?;
+                    return Ok(());
+                }
 
+                if is_command(&input, "cancel") {
+                    let reply = if agent.cancel(&namespace, "Cancelled with /cancel.") {
+                        "Stopping."
+                    } else {
+                        "Nothing to cancel."
+                    };
+                    bot.send_message(msg.chat.id, reply).await?;
+                    return Ok(());
+                }
+
+                // Updates from one chat are handled in order, so answering here
+                // would hold back a /cancel sent while the reply is streaming
+                tokio::spawn(async move {
+                    if let Err(e) = stream_reply(&bot, msg.chat.id, &agent, &input, &namespace).await {
+                        eprintln!("⚠️  Failed to send Telegram reply: {}", e);
+                    }
+                });
                 Ok(())
             }
         }
@@ -35,6 +72,148 @@ pub async fn start(agent: Adme) {
     .await;
 }
 
+/// Sends the reply as soon as its first words arrive and keeps editing it as the rest streams in.
+///
+/// A reply too long for one message carries on in a new one.
+async fn stream_reply(
+    bot: &Bot,
+    chat_id: ChatId,
+    agent: &Adme,
+    input: &str,
+    namespace: &Namespace,
+) -> ResponseResult<()> {
+    let mut tokens = agent.prompt_stream(input, namespace);
+    let mut reply = String::new();
+    let mut sent: Option<(MessageId, String)> = None;
+    let mut last_edit = Instant::now();
+
+    while let Some(token) = tokens.recv().await {
+        match token {
+            Ok(text) => reply.push_str(&text),
+            Err(e) => {
+                if !reply.is_empty() {
+                    reply.push_str("\n\n");
+                }
+                reply.push_str(&format!("⚠️ {}", e.user_message()));
+            }
+        }
+        while let Some((full, rest)) = split_message(&reply) {
+            show_reply(bot, chat_id, sent, &full).await?;
+            sent = None;
+            reply = rest;
+        }
+        if sent.is_none() || last_edit.elapsed() >= EDIT_INTERVAL {
+            sent = show_reply(bot, chat_id, sent, &reply).await?;
+            last_edit = Instant::now();
+        }
+    }
+
+    if sent.is_none() && reply.trim().is_empty() {
+        reply = String::from("…");
+    }
+    show_reply(bot, chat_id, sent, &reply).await?;
+    Ok(())
+}
+
+/// Sends `reply`, or edits the already sent message if the text has changed.
+async fn show_reply(
+    bot: &Bot,
+    chat_id: ChatId,
+    sent: Option<(MessageId, String)>,
+    reply: &str,
+) -> ResponseResult<Option<(MessageId, String)>> {
+    // Telegram rejects empty messages and edits that change nothing
+    let text = reply.trim();
+    if text.is_empty() {
+        return Ok(sent);
+    }
+
+    match sent {
+        Some((id, shown)) => {
+            if shown != text {
+                bot.edit_message_text(chat_id, id, text).await?;
+            }
+            Ok(Some((id, text.to_string())))
+        }
+        None => {
+            let message = bot.send_message(chat_id, text).await?;
+            Ok(Some((message.id, text.to_string())))
+        }
+    }
+}
+
+/// Whether `input` is the command `name`, with or without the `@botname`
+/// Telegram appends to commands sent in group chats.
+fn is_command(input: &str, name: &str) -> bool {
+    let command = input.split_once('@').map_or(input, |(command, _)| command);
+    command.strip_prefix('/').is_some_and(|command| command.eq_ignore_ascii_case(name))
+}
+
+/// Splits `reply` into a message's worth and the rest, breaking at a line or
+/// word if there is one in the second half, or `None` if it fits in one.
+fn split_message(reply: &str) -> Option<(String, String)> {
+    let (limit, _) = reply.char_indices().nth(MAX_MESSAGE_CHARS)?;
+    let head = &reply[..limit];
+    let end = head
+        .rfind('\n')
+        .or_else(|| head.rfind(' '))
+        .filter(|&end| end > limit / 2)
+        .unwrap_or(limit);
+    Some((reply[..end].to_string(), reply[end..].to_string()))
+}
+
+/// Summarises what each stage did for the last prompt, short enough for one message.
+fn describe_trace(trace: &Trace) -> String {
+    let mut text = format!(
+        "Prompt: {}\nTook {:.1}s\n",
+        truncate(&trace.prompt, TRACE_FIELD_CHARS),
+        trace.duration_ms as f64 / 1000.0
+    );
+    for stage in &trace.stages {
+        if stage.skipped {
+            text.push_str(&format!("\n⏭ {} skipped\n", stage.id));
+            continue;
+        }
+        if stage.in_progress {
+            text.push_str(&format!("\n⏳ {} didn't finish\n", stage.id));
+            continue;
+        }
+        let status = if stage.error.is_some() { "❌" } else { "✅" };
+        text.push_str(&format!("\n{} {} ({:.1}s", status, stage.id, stage.duration_ms as f64 / 1000.0));
+        if let Some(usage) = stage.usage {
+            text.push_str(&format!(", {} in / {} out tokens", usage.input_tokens, usage.output_tokens));
+        }
+        text.push_str(")\n");
+        for call in &stage.tool_calls {
+            let result = call.result.as_deref().or(call.error.as_deref()).unwrap_or_default();
+            text.push_str(&format!(
+                "🔧 {}({}) → {}\n",
+                call.name,
+                truncate(&call.arguments, TRACE_FIELD_CHARS),
+                truncate(result, TRACE_FIELD_CHARS)
+            ));
+        }
+        if let Some(output) = &stage.output {
+            text.push_str(&format!("Output: {}\n", truncate(output, TRACE_FIELD_CHARS)));
+        }
+        if let Some(error) = &stage.error {
+            text.push_str(&format!("Error: {}\n", truncate(error, TRACE_FIELD_CHARS)));
+        }
+    }
+    if let Some(error) = &trace.error {
+        text.push_str(&format!("\n⚠️ {}\n", error));
+    }
+    truncate(&text, MAX_MESSAGE_CHARS)
+}
+
+/// The first `max` characters of `text`, with an ellipsis if anything was cut.
+fn truncate(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
 fn validate_telegram_user_id(msg: &Message) -> bool {
     if let Some(user) = &msg.from {
         let user_id = user.id;
@@ -52,3 +231,40 @@ fn validate_telegram_user_id(msg: &Message) -> bool {
         false
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commands_ignore_bot_name() {
+        assert!(is_command("/cancel", "cancel"));
+        assert!(is_command("/Cancel@seedling_bot", "cancel"));
+        assert!(!is_command("/cancellation", "cancel"));
+        assert!(!is_command("cancel", "cancel"));
+        assert!(!is_command("/reset@seedling_bot", "cancel"));
+    }
+
+    #[test]
+    fn test_short_replies_fit_in_one_message() {
+        assert_eq!(split_message("Hello there"), None);
+        assert_eq!(split_message(&"a".repeat(MAX_MESSAGE_CHARS)), None);
+    }
+
+    #[test]
+    fn test_long_replies_break_between_words() {
+        let reply = "word ".repeat(MAX_MESSAGE_CHARS);
+        let (full, rest) = split_message(&reply).unwrap();
+        assert!(full.chars().count() <= MAX_MESSAGE_CHARS);
+        assert!(full.ends_with("word"));
+        assert_eq!(format!("{}{}", full, rest), reply);
+    }
+
+    #[test]
+    fn test_unbroken_replies_are_cut_at_the_limit() {
+        let reply = "é".repeat(MAX_MESSAGE_CHARS + 10);
+        let (full, rest) = split_message(&reply).unwrap();
+        assert_eq!(full.chars().count(), MAX_MESSAGE_CHARS);
+        assert_eq!(rest.chars().count(), 10);
+    }
+}
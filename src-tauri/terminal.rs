@@ -6,7 +6,7 @@ use std::sync::Arc;
 use tauri::{AppHandle, Emitter, State};
 
 use crate::{
-    adme::{self, Adme},
+    adme::{Adme, Namespace},
 };
 
 pub struct PtySession {
@@ -78,6 +78,17 @@ pub async fn write_to_buffer(
     agent: State<'_, Adme>,
     app: AppHandle,
 ) -> Result<(), String> {
+    // The desktop terminal is a single local conversation
+    let namespace = Namespace::for_conversation("local", "terminal");
+
+    // Ctrl+C drops the line being typed and stops the reply in progress
+    if data.contains('\x03') {
+        state.0.lock().unwrap().input_buffer.clear();
+        app.emit("pty-data", "^C\r\n").map_err(|e| e.to_string())?;
+        agent.cancel(&namespace, "Interrupted with Ctrl+C.");
+        return Ok(());
+    }
+
     // If no newline, buffer and echo back to terminal
     if !data.contains('\r') {
         let mut state_guard = state.0.lock().unwrap();
@@ -101,13 +112,20 @@ pub async fn write_to_buffer(
     // Echo the newline
     app.emit("pty-data", "\r\n").map_err(|e| e.to_string())?;
 
-    let response = agent.prompt(&input).await;
+    let mut tokens = agent.prompt_stream(&input, &namespace);
+    while let Some(token) = tokens.recv().await {
+        let text = match token {
+            Ok(text) => text,
+            // Shown in red so it stands out from the reply
+            Err(e) => format!("\x1b[31m{}\x1b[0m", e.user_message()),
+        };
 
-    // Convert Unix newlines to terminal newlines
-    let terminal_response = response.replace("\n", "\r\n");
+        // Convert Unix newlines to terminal newlines
+        app.emit("pty-data", text.replace("\n", "\r\n"))
+            .map_err(|e| e.to_string())?;
+    }
 
-    app.emit("pty-data", format!("{}\r\n", terminal_response))
-        .map_err(|e| e.to_string())?;
+    app.emit("pty-data", "\r\n").map_err(|e| e.to_string())?;
 
     Ok(())
 }